@@ -1,6 +1,7 @@
 import { Connection, PublicKey, Transaction, SystemProgram, LAMPORTS_PER_SOL } from "@solana/web3.js";
 import { Program, AnchorProvider, Idl, web3 } from "@coral-xyz/anchor";
 import { getAssociatedTokenAddress, TOKEN_PROGRAM_ID, ASSOCIATED_TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import { getSessionPda } from "@solvpn/sdk/pda";
 
 // Configuration
 export const SOLANA_RPC = process.env.NEXT_PUBLIC_SOLANA_RPC || "https://api.devnet.solana.com";
@@ -14,16 +15,66 @@ export interface NodeData {
   stakeLamports: number;
   totalBytesRelayed: number;
   unclaimedReward: number;
+  ratingSum: number;
+  ratingCount: number;
+  active: boolean;
+  lastHeartbeatTs: number;
+  commissionBps: number;
+  delegatedLamports: number;
+  stakedTokens: number;
+  metadata: NodeMetadata;
 }
 
+// Bits of NodeMetadata.protocols
+export const PROTOCOL_WIREGUARD = 1 << 0;
+export const PROTOCOL_OPENVPN = 1 << 1;
+export const PROTOCOL_IKEV2 = 1 << 2;
+export const PROTOCOL_SOCKS5 = 1 << 3;
+
+export interface NodeMetadata {
+  region: string; // ISO 3166-1 alpha-2, "" if unset
+  endpointPort: number;
+  protocols: number;
+  maxSessions: number; // 0 = unlimited
+  endpointHost: string;
+}
+
+// Node account offsets, mirroring the field order of `Node` in programs/dvpn.
+// `paused` ends at 634: 8 discriminator + 209 fixed fields + 8 * 49 slash history + 24 pricing + 8 + 1
+const NODE_PAUSED_END = 634;
+const NODE_LAST_HEARTBEAT_OFFSET = NODE_PAUSED_END;
+const NODE_COMMISSION_OFFSET = NODE_LAST_HEARTBEAT_OFFSET + 8 + 8 + 1; // after uptime_secs, marked_stale
+const NODE_DELEGATED_OFFSET = NODE_COMMISSION_OFFSET + 2;
+const NODE_STAKED_TOKENS_OFFSET = NODE_DELEGATED_OFFSET + 8 + 8 + 16; // after shares, reward per share
+// token unbonding (8 + 8), slashed_tokens (8), 4 payment rates (40 each), unclaimed_epochs (4)
+const NODE_METADATA_OFFSET = NODE_STAKED_TOKENS_OFFSET + 8 + 8 + 8 + 8 + 4 * 40 + 4;
+
+function parseNodeMetadata(data: Buffer): NodeMetadata {
+  const o = NODE_METADATA_OFFSET;
+  const hostLen = data.readUInt32LE(o + 10);
+  return {
+    region: data[o] === 0 ? "" : data.slice(o, o + 2).toString("ascii"),
+    endpointPort: data.readUInt16LE(o + 2),
+    protocols: data.readUInt16LE(o + 4),
+    maxSessions: data.readUInt32LE(o + 6),
+    endpointHost: data.slice(o + 14, o + 14 + hostLen).toString("ascii"),
+  };
+}
+
+// Mirrors the on-chain SessionStatus enum
+export type SessionStatus = "active" | "settled" | "pending" | "disputed" | "cancelled";
+const SESSION_STATUSES: SessionStatus[] = ["active", "settled", "pending", "disputed", "cancelled"];
+
 export interface SessionData {
   user: PublicKey;
   node: PublicKey;
   depositAmount: number;
   bytesUsed: number;
   startedAt: number;
-  closed: boolean;
+  status: SessionStatus;
   bump: number;
+  sessionId: bigint;
+  paymentMint: PublicKey;
 }
 
 export interface StateData {
@@ -49,9 +100,9 @@ export function getNodePda(operator: PublicKey): [PublicKey, number] {
   );
 }
 
-export function getSessionPda(user: PublicKey, node: PublicKey): [PublicKey, number] {
+export function getDelegationPda(node: PublicKey, delegator: PublicKey): [PublicKey, number] {
   return PublicKey.findProgramAddressSync(
-    [Buffer.from("session"), user.toBuffer(), node.toBuffer()],
+    [Buffer.from("delegation"), node.toBuffer(), delegator.toBuffer()],
     PROGRAM_ID
   );
 }
@@ -85,6 +136,14 @@ export async function fetchAllNodes(connection: Connection): Promise<Array<{ pub
             stakeLamports: Number(data.readBigUInt64LE(76)),
             totalBytesRelayed: Number(data.readBigUInt64LE(84)),
             unclaimedReward: Number(data.readBigUInt64LE(92)),
+            ratingSum: Number(data.readBigUInt64LE(132)),
+            ratingCount: data.readUInt32LE(140),
+            active: data[144] === 1,
+            lastHeartbeatTs: Number(data.readBigInt64LE(NODE_LAST_HEARTBEAT_OFFSET)),
+            commissionBps: data.readUInt16LE(NODE_COMMISSION_OFFSET),
+            delegatedLamports: Number(data.readBigUInt64LE(NODE_DELEGATED_OFFSET)),
+            stakedTokens: Number(data.readBigUInt64LE(NODE_STAKED_TOKENS_OFFSET)),
+            metadata: parseNodeMetadata(data),
           },
         };
       } catch (e) {
@@ -116,12 +175,11 @@ export async function startSession(
   connection: Connection,
   wallet: any,
   nodeOperator: PublicKey,
-  depositAmount: number
+  depositAmount: number,
+  paymentMint?: PublicKey
 ): Promise<string> {
   const user = wallet.publicKey;
   const [nodePda] = getNodePda(nodeOperator);
-  const [sessionPda] = getSessionPda(user, nodePda);
-  const [statePda] = getStatePda(user); // Simplified - use proper authority
 
   // Build transaction via attestor service
   const response = await fetch(`${ATTESTOR_URL}/start-session-tx`, {
@@ -131,6 +189,7 @@ export async function startSession(
       user: user.toBase58(),
       node: nodePda.toBase58(),
       depositAmount,
+      paymentMint: paymentMint?.toBase58(),
     }),
   });
 
@@ -145,11 +204,11 @@ export async function startSession(
 export async function settleSession(
   connection: Connection,
   wallet: any,
-  nodeOperator: PublicKey
+  nodeOperator: PublicKey,
+  sessionId?: bigint
 ): Promise<string> {
   const user = wallet.publicKey;
   const [nodePda] = getNodePda(nodeOperator);
-  const [sessionPda] = getSessionPda(user, nodePda);
 
   const response = await fetch(`${ATTESTOR_URL}/settle-session-tx`, {
     method: "POST",
@@ -157,6 +216,7 @@ export async function settleSession(
     body: JSON.stringify({
       user: user.toBase58(),
       node: nodePda.toBase58(),
+      sessionId: sessionId?.toString(),
     }),
   });
 
@@ -165,30 +225,143 @@ export async function settleSession(
   return signed.signature || signed;
 }
 
-// Fetch session data
-export async function fetchSession(
-  connection: Connection,
-  user: PublicKey,
-  nodeOperator: PublicKey
-): Promise<SessionData | null> {
+// Message the user signs to acknowledge cumulative usage in payment-channel mode:
+// "dvpn:receipt:v2" || session || cumulative_bytes (u64 LE) || expires_at (i64 LE)
+export function receiptMessage(session: PublicKey, cumulativeBytes: bigint, expiresAt: bigint): Uint8Array {
+  const bytes = Buffer.alloc(8);
+  bytes.writeBigUInt64LE(cumulativeBytes);
+  const expiry = Buffer.alloc(8);
+  expiry.writeBigInt64LE(expiresAt);
+  return Buffer.concat([Buffer.from("dvpn:receipt:v2"), session.toBuffer(), bytes, expiry]);
+}
+
+// How long a signed receipt stays valid for the node to settle with
+const RECEIPT_TTL_SECS = 10 * 60;
+
+// Sign a usage receipt for the node; receipts must only ever grow
+export async function signUsageReceipt(
+  wallet: any,
+  session: PublicKey,
+  cumulativeBytes: bigint
+): Promise<{ cumulativeBytes: string; expiresAt: string; signature: string }> {
+  const expiresAt = BigInt(Math.floor(Date.now() / 1000) + RECEIPT_TTL_SECS);
+  const { signature } = await wallet.signMessage(receiptMessage(session, cumulativeBytes, expiresAt));
+  return {
+    cumulativeBytes: cumulativeBytes.toString(),
+    expiresAt: expiresAt.toString(),
+    signature: Buffer.from(signature).toString("base64"),
+  };
+}
+
+// Pay out a pending session after its dispute window (defaults to the user's most recent session)
+export async function finalizeSession(
+  wallet: any,
+  nodeOperator: PublicKey,
+  sessionId?: bigint
+): Promise<string> {
+  return sendAttestorTx(wallet, "finalize-session-tx", nodeOperator, { sessionId: sessionId?.toString() });
+}
+
+// Contest a pending session's usage with the user's own byte count
+export async function disputeSession(
+  wallet: any,
+  nodeOperator: PublicKey,
+  userBytes: bigint,
+  sessionId?: bigint
+): Promise<string> {
+  return sendAttestorTx(wallet, "dispute-session-tx", nodeOperator, {
+    userBytes: userBytes.toString(),
+    sessionId: sessionId?.toString(),
+  });
+}
+
+async function sendAttestorTx(
+  wallet: any,
+  endpoint: string,
+  nodeOperator: PublicKey,
+  extra: Record<string, unknown>
+): Promise<string> {
   const [nodePda] = getNodePda(nodeOperator);
-  const [sessionPda] = getSessionPda(user, nodePda);
-  const accountInfo = await connection.getAccountInfo(sessionPda);
-  
-  if (!accountInfo) return null;
+  const response = await fetch(`${ATTESTOR_URL}/${endpoint}`, {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify({ user: wallet.publicKey.toBase58(), node: nodePda.toBase58(), ...extra }),
+  });
 
-  const data = accountInfo.data;
+  const { tx } = await response.json();
+  const signed = await wallet.signAndSendTransaction(tx);
+  return signed.signature || signed;
+}
+
+// Rate the node of a settled session, 1-5 stars (defaults to the user's most recent session)
+export async function rateNode(
+  wallet: any,
+  nodeOperator: PublicKey,
+  rating: number,
+  sessionId?: bigint
+): Promise<string> {
+  const user = wallet.publicKey;
+  const [nodePda] = getNodePda(nodeOperator);
+
+  const response = await fetch(`${ATTESTOR_URL}/rate-node-tx`, {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify({
+      user: user.toBase58(),
+      node: nodePda.toBase58(),
+      rating,
+      sessionId: sessionId?.toString(),
+    }),
+  });
+
+  const { tx } = await response.json();
+  const signed = await wallet.signAndSendTransaction(tx);
+  return signed.signature || signed;
+}
+
+function parseSession(data: Buffer): SessionData {
   return {
     user: new PublicKey(data.slice(8, 40)),
     node: new PublicKey(data.slice(40, 72)),
     depositAmount: Number(data.readBigUInt64LE(72)),
     bytesUsed: Number(data.readBigUInt64LE(80)),
     startedAt: Number(data.readBigInt64LE(88)),
-    closed: data[96] === 1,
+    status: SESSION_STATUSES[data[96]],
     bump: data[97],
+    sessionId: data.readBigUInt64LE(130),
+    paymentMint: new PublicKey(data.slice(187, 219)),
   };
 }
 
+// Fetch session data. Without a sessionId, returns the user's most recent session with this
+// node: session ids come from a per-user counter shared across nodes, so the latest id
+// may belong to another node and the sessions are scanned by user and node instead.
+export async function fetchSession(
+  connection: Connection,
+  user: PublicKey,
+  nodeOperator: PublicKey,
+  sessionId?: bigint
+): Promise<SessionData | null> {
+  const [nodePda] = getNodePda(nodeOperator);
+  if (sessionId === undefined) {
+    const accounts = await connection.getProgramAccounts(PROGRAM_ID, {
+      filters: [
+        { memcmp: { offset: 8, bytes: user.toBase58() } },
+        { memcmp: { offset: 40, bytes: nodePda.toBase58() } },
+      ],
+    });
+    return accounts
+      .map((account) => parseSession(account.account.data))
+      .reduce<SessionData | null>((latest, s) => (!latest || s.sessionId > latest.sessionId ? s : latest), null);
+  }
+  const [sessionPda] = getSessionPda(user, nodePda, sessionId, PROGRAM_ID);
+  const accountInfo = await connection.getAccountInfo(sessionPda);
+  
+  if (!accountInfo) return null;
+
+  return parseSession(accountInfo.data);
+}
+
 // Get token balance
 export async function getTokenBalance(
   connection: Connection,
@@ -196,7 +369,9 @@ export async function getTokenBalance(
   mint: PublicKey
 ): Promise<number> {
   try {
-    const ata = await getAssociatedTokenAddress(mint, wallet);
+    // The mint may live under SPL Token or Token-2022; the ATA address depends on which
+    const mintInfo = await connection.getAccountInfo(mint);
+    const ata = await getAssociatedTokenAddress(mint, wallet, false, mintInfo?.owner ?? TOKEN_PROGRAM_ID);
     const balance = await connection.getTokenAccountBalance(ata);
     return Number(balance.value.amount) / Math.pow(10, balance.value.decimals);
   } catch (e) {
@@ -0,0 +1,59 @@
+pragma circom 2.1.5;
+
+include "circomlib/circuits/poseidon.circom";
+
+// Bandwidth proof bound to an on-chain session, verified by dvpn::settle_session_with_proof.
+// Public signals (in order): total, sessionNonce, nodeCommitment, usageCommitment
+//   total           - sum of the per-interval byte counters
+//   sessionNonce    - first 31 bytes of the Session account address (big-endian)
+//   nodeCommitment  - Node.zk_commitment
+//   usageCommitment - commitment to the intervals, signed by the session user on-chain
+// Private inputs: intervals[N], nodeSecret
+// Constraint: nodeCommitment == Poseidon(nodeSecret), so only the node can prove
+// Constraint: usageCommitment == Poseidon(sessionNonce, intervals[0..N]), so the counters
+//   are the ones the user signed for this session and the node cannot pick the total
+// Constraint: every interval fits in 32 bits so the sum cannot wrap the field
+
+template SessionBandwidth(N) {
+    // Public
+    signal input sessionNonce;
+    signal input nodeCommitment;
+    signal input usageCommitment;
+    signal output total;
+
+    // Private
+    signal input intervals[N];
+    signal input intervalBits[N][32];
+    signal input nodeSecret;
+
+    component nodeHash = Poseidon(1);
+    nodeHash.inputs[0] <== nodeSecret;
+    nodeHash.out === nodeCommitment;
+
+    component usageHash = Poseidon(N + 1);
+    usageHash.inputs[0] <== sessionNonce;
+    for (var i = 0; i < N; i++) {
+        usageHash.inputs[i + 1] <== intervals[i];
+    }
+    usageHash.out === usageCommitment;
+
+    signal partial[N][33];
+    signal terms[N][32];
+    for (var i = 0; i < N; i++) {
+        partial[i][0] <== 0;
+        for (var j = 0; j < 32; j++) {
+            intervalBits[i][j] * (intervalBits[i][j] - 1) === 0;
+            terms[i][j] <== intervalBits[i][j] * (1 << j);
+            partial[i][j + 1] <== partial[i][j] + terms[i][j];
+        }
+        partial[i][32] === intervals[i];
+    }
+
+    var sum = 0;
+    for (var i = 0; i < N; i++) {
+        sum += intervals[i];
+    }
+    total <== sum;
+}
+
+component main {public [sessionNonce, nodeCommitment, usageCommitment]} = SessionBandwidth(8);
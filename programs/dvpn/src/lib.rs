@@ -1,7 +1,16 @@
 use anchor_lang::prelude::*;
-use anchor_spl::associated_token::AssociatedToken;
-use anchor_spl::token::{self, Mint, MintTo, Token, TokenAccount, Transfer};
-use anchor_lang::solana_program::{program::invoke, system_instruction};
+use anchor_lang::Discriminator;
+use anchor_spl::associated_token::{get_associated_token_address_with_program_id, AssociatedToken};
+use anchor_spl::token_2022::spl_token_2022::extension::transfer_fee::TransferFeeConfig;
+use anchor_spl::token_2022::spl_token_2022::extension::{BaseStateWithExtensions, StateWithExtensions};
+use anchor_spl::token_interface::{
+    self, harvest_withheld_tokens_to_mint, CloseAccount, HarvestWithheldTokensToMint, Mint, MintTo, TokenAccount,
+    TokenInterface, TransferChecked,
+};
+use anchor_lang::solana_program::{ed25519_program, hash::hashv, program::invoke, system_instruction};
+use anchor_lang::solana_program::alt_bn128::prelude::{alt_bn128_addition, alt_bn128_multiplication, alt_bn128_pairing};
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::sysvar::instructions::{load_current_index_checked, load_instruction_at_checked};
 
 declare_id!("8j3TUcbSuaq5BVNSf5GJhgucwrswH432sqJNxCoym8hB");
 
@@ -16,22 +25,364 @@ pub mod dvpn {
         state.attestor = ctx.accounts.authority.key();
         state.mint = ctx.accounts.dvpn_mint.key();
         state.reward_rate_bps = reward_rate_bps;
+        state.min_stake_lamports = 0;
+        state.protocol_fee_bps = 100;
+        state.fee_treasury = Pubkey::default();
+        state.admin = ctx.accounts.authority.key();
+        state.pending_admin = Pubkey::default();
+        state.pauser = ctx.accounts.authority.key();
+        state.fee_manager = ctx.accounts.authority.key();
+        state.attestor_manager = ctx.accounts.authority.key();
+        state.paused = false;
+        state.timelock_delay_secs = DEFAULT_TIMELOCK_DELAY_SECS;
+        state.next_proposal_id = 0;
+        state.max_supply = token_amount(DEFAULT_MAX_SUPPLY_TOKENS, ctx.accounts.dvpn_mint.decimals)?;
+        state.emission_allocated = 0;
+        state.next_epoch_id = 0;
+        state.last_epoch_end = 0;
+        state.reward_params = RewardParams::DEFAULT;
+        state.dispute_window_secs = DEFAULT_DISPUTE_WINDOW_SECS;
+        state.dispute_bond = token_amount(DEFAULT_DISPUTE_BOND_TOKENS, ctx.accounts.dvpn_mint.decimals)?;
+        state.dispute_slash_lamports = DEFAULT_DISPUTE_SLASH_LAMPORTS;
+        state.heartbeat_staleness_secs = DEFAULT_HEARTBEAT_STALENESS_SECS;
+        state.token_stake_rate = 0;
+        state.payment_mints = [Pubkey::default(); MAX_PAYMENT_MINTS];
+        state.payment_mints[0] = state.mint;
+        state.total_vault_tokens = 0;
+
+        let treasury = &mut ctx.accounts.treasury;
+        treasury.state = state.key();
+        treasury.total_slashed_lamports = 0;
+        treasury.bump = ctx.bumps.treasury;
+        treasury.total_fees_collected = 0;
+        treasury.total_fees_withdrawn = 0;
+        Ok(())
+    }
+
+    /// Grow a `State` created by an older program version to the current layout.
+    /// New fields start zeroed; unset roles default to the original authority and
+    /// unset timelock, reward, dispute and heartbeat parameters to their defaults. An empty payment
+    /// mint allowlist is seeded with `state.mint`. `max_supply` stays 0 (no emission) until raised
+    /// through a `SetMaxSupply` proposal, and `dispute_bond` stays 0 (no disputes) until set with
+    /// `set_dispute_config`, since it depends on the mint's decimals. The treasury PDA is created if
+    /// it is missing.
+    pub fn migrate_state(ctx: Context<MigrateState>) -> Result<()> {
+        let state_info = ctx.accounts.state.to_account_info();
+        {
+            let data = state_info.try_borrow_data()?;
+            require!(data.len() >= 8 + 32 && data[..8] == State::DISCRIMINATOR, DvpnError::InvalidStateAccount);
+            require!(data[8..40] == ctx.accounts.authority.key().to_bytes(), DvpnError::Unauthorized);
+        }
+
+        let new_len = 8 + State::SIZE;
+        if state_info.data_len() < new_len {
+            let required = Rent::get()?.minimum_balance(new_len);
+            let shortfall = required.saturating_sub(state_info.lamports());
+            if shortfall > 0 {
+                invoke(
+                    &system_instruction::transfer(&ctx.accounts.authority.key(), state_info.key, shortfall),
+                    &[
+                        ctx.accounts.authority.to_account_info(),
+                        state_info.clone(),
+                        ctx.accounts.system_program.to_account_info(),
+                    ],
+                )?;
+            }
+            state_info.realloc(new_len, true)?;
+        }
+
+        let mut state = State::try_deserialize(&mut &state_info.try_borrow_data()?[..])?;
+        for role in [&mut state.admin, &mut state.pauser, &mut state.fee_manager, &mut state.attestor_manager] {
+            if *role == Pubkey::default() {
+                *role = state.authority;
+            }
+        }
+        if state.timelock_delay_secs == 0 {
+            state.timelock_delay_secs = DEFAULT_TIMELOCK_DELAY_SECS;
+        }
+        if state.reward_params.max_multiplier_bps == 0 {
+            state.reward_params = RewardParams::DEFAULT;
+        }
+        if state.dispute_window_secs == 0 {
+            state.dispute_window_secs = DEFAULT_DISPUTE_WINDOW_SECS;
+        }
+        if state.dispute_slash_lamports == 0 {
+            state.dispute_slash_lamports = DEFAULT_DISPUTE_SLASH_LAMPORTS;
+        }
+        if state.heartbeat_staleness_secs == 0 {
+            state.heartbeat_staleness_secs = DEFAULT_HEARTBEAT_STALENESS_SECS;
+        }
+        if state.payment_mints.iter().all(|m| *m == Pubkey::default()) {
+            state.payment_mints[0] = state.mint;
+        }
+        state.try_serialize(&mut &mut state_info.try_borrow_mut_data()?[..])?;
+
+        let treasury = &mut ctx.accounts.treasury;
+        if treasury.state == Pubkey::default() {
+            treasury.state = state_info.key();
+            treasury.bump = ctx.bumps.treasury;
+        }
+        Ok(())
+    }
+
+    /// Grow a `Node` created by an older program version to the current layout (operator only).
+    /// Fields added since are inserted before `metadata`, which is always last, so existing data
+    /// keeps its offsets, `metadata` moves to the new end and new fields start zeroed: no
+    /// heartbeat yet, no delegations or token stake and no extra payment rates.
+    pub fn migrate_node(ctx: Context<MigrateNode>) -> Result<()> {
+        let node_info = ctx.accounts.node.to_account_info();
+        {
+            let data = node_info.try_borrow_data()?;
+            require!(data.len() >= 8 + 32 && data[..8] == Node::DISCRIMINATOR, DvpnError::InvalidNodeAccount);
+            require!(data[8..40] == ctx.accounts.operator.key().to_bytes(), DvpnError::Unauthorized);
+        }
+
+        let old_len = node_info.data_len();
+        let new_len = 8 + Node::SIZE;
+        if old_len < new_len {
+            // Accounts from before `metadata` end at `paused`; later ones carry it as the last field
+            let metadata = if old_len >= Node::LEGACY_METADATA_OFFSET + NodeMetadata::SIZE {
+                let data = node_info.try_borrow_data()?;
+                Some(NodeMetadata::deserialize(&mut &data[old_len - NodeMetadata::SIZE..])?)
+            } else {
+                None
+            };
+            let required = Rent::get()?.minimum_balance(new_len);
+            let shortfall = required.saturating_sub(node_info.lamports());
+            if shortfall > 0 {
+                invoke(
+                    &system_instruction::transfer(&ctx.accounts.operator.key(), node_info.key, shortfall),
+                    &[
+                        ctx.accounts.operator.to_account_info(),
+                        node_info.clone(),
+                        ctx.accounts.system_program.to_account_info(),
+                    ],
+                )?;
+            }
+            node_info.realloc(new_len, true)?;
+
+            if let Some(metadata) = metadata {
+                let mut data = node_info.try_borrow_mut_data()?;
+                data[old_len - NodeMetadata::SIZE..].fill(0);
+                metadata.serialize(&mut &mut data[Node::METADATA_OFFSET..])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// First step of an admin handover: nominate the next admin (admin only)
+    pub fn propose_authority(ctx: Context<ProposeAuthority>, new_admin: Pubkey) -> Result<()> {
+        require_keys_eq!(ctx.accounts.state.admin, ctx.accounts.admin.key(), DvpnError::Unauthorized);
+        ctx.accounts.state.pending_admin = new_admin;
+        emit!(AuthorityProposed {
+            current: ctx.accounts.state.admin,
+            proposed: new_admin,
+        });
+        Ok(())
+    }
+
+    /// Second step of an admin handover, signed by the nominated key
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+        require!(state.pending_admin != Pubkey::default(), DvpnError::NoPendingAuthority);
+        require_keys_eq!(state.pending_admin, ctx.accounts.new_admin.key(), DvpnError::Unauthorized);
+        let previous = state.admin;
+        state.admin = state.pending_admin;
+        state.pending_admin = Pubkey::default();
+        emit!(AuthorityTransferred {
+            previous,
+            admin: state.admin,
+        });
+        Ok(())
+    }
+
+    /// Assign a non-admin role (admin only)
+    pub fn set_role(ctx: Context<SetRole>, role: Role, holder: Pubkey) -> Result<()> {
+        require_keys_eq!(ctx.accounts.state.admin, ctx.accounts.admin.key(), DvpnError::Unauthorized);
+        let state = &mut ctx.accounts.state;
+        match role {
+            Role::Pauser => state.pauser = holder,
+            Role::FeeManager => state.fee_manager = holder,
+            Role::AttestorManager => state.attestor_manager = holder,
+        }
+        emit!(RoleUpdated { role, holder });
+        Ok(())
+    }
+
+    /// Emergency stop for the whole program (pauser only). Refund and cancel
+    /// paths stay open so deposits can always be recovered.
+    pub fn set_paused(ctx: Context<SetPaused>, paused: bool) -> Result<()> {
+        require_keys_eq!(ctx.accounts.state.pauser, ctx.accounts.pauser.key(), DvpnError::Unauthorized);
+        ctx.accounts.state.paused = paused;
+        emit!(PauseUpdated { node: None, paused });
+        Ok(())
+    }
+
+    /// Halt sessions, usage, rewards and settlement for a single node (pauser only)
+    pub fn set_node_paused(ctx: Context<SetNodePaused>, paused: bool) -> Result<()> {
+        require_keys_eq!(ctx.accounts.state.pauser, ctx.accounts.pauser.key(), DvpnError::Unauthorized);
+        ctx.accounts.node.paused = paused;
+        emit!(PauseUpdated {
+            node: Some(ctx.accounts.node.key()),
+            paused,
+        });
+        Ok(())
+    }
+
+    /// Queue a parameter change that can be executed once the timelock delay has passed.
+    /// The signer must hold the role that owns the parameter (see `GovernanceAction::role_holder`).
+    pub fn queue_proposal(ctx: Context<QueueProposal>, action: GovernanceAction) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+        require_keys_eq!(action.role_holder(state), ctx.accounts.proposer.key(), DvpnError::Unauthorized);
+        action.validate()?;
+
+        let eta = Clock::get()?
+            .unix_timestamp
+            .checked_add(state.timelock_delay_secs)
+            .ok_or(DvpnError::MathOverflow)?;
+        let proposal = &mut ctx.accounts.proposal;
+        proposal.state = state.key();
+        proposal.id = state.next_proposal_id;
+        proposal.proposer = ctx.accounts.proposer.key();
+        proposal.action = action;
+        proposal.eta = eta;
+        proposal.status = ProposalStatus::Queued;
+        proposal.bump = ctx.bumps.proposal;
+        state.next_proposal_id = state.next_proposal_id.checked_add(1).ok_or(DvpnError::MathOverflow)?;
+
+        emit!(ProposalQueued {
+            proposal: proposal.key(),
+            id: proposal.id,
+            action,
+            eta,
+        });
+        Ok(())
+    }
+
+    /// Apply a queued proposal after its `eta` and before the grace period runs out (permissionless)
+    pub fn execute_proposal(ctx: Context<ExecuteProposal>) -> Result<()> {
+        let proposal = &mut ctx.accounts.proposal;
+        require!(proposal.status == ProposalStatus::Queued, DvpnError::ProposalNotQueued);
+        let now = Clock::get()?.unix_timestamp;
+        require!(now >= proposal.eta, DvpnError::ProposalNotReady);
+        require!(
+            now <= proposal.eta.saturating_add(PROPOSAL_GRACE_PERIOD_SECS),
+            DvpnError::ProposalExpired
+        );
+
+        let state = &mut ctx.accounts.state;
+        let registry = &mut ctx.accounts.attestor_registry;
+        match proposal.action {
+            GovernanceAction::SetMaxSupply { max_supply } => {
+                require!(max_supply >= state.emission_allocated, DvpnError::SupplyCapExceeded);
+                state.max_supply = max_supply;
+            }
+            GovernanceAction::SetProtocolFee { protocol_fee_bps } => {
+                state.protocol_fee_bps = protocol_fee_bps;
+                emit!(FeeConfigUpdated {
+                    protocol_fee_bps,
+                    fee_treasury: state.fee_treasury,
+                });
+            }
+            GovernanceAction::SetMint { mint } => {
+                // Stake and fees already held in the old mint would be stranded
+                require!(state.total_vault_tokens == 0, DvpnError::TokenStakeOutstanding);
+                if state.fee_treasury != Pubkey::default() {
+                    let fee_treasury = ctx.accounts.fee_treasury.as_ref().ok_or(DvpnError::InvalidTreasury)?;
+                    require!(fee_treasury.amount == 0, DvpnError::TreasuryNotEmpty);
+                    state.fee_treasury = Pubkey::default();
+                    emit!(FeeConfigUpdated {
+                        protocol_fee_bps: state.protocol_fee_bps,
+                        fee_treasury: state.fee_treasury,
+                    });
+                }
+                let old_mint = state.mint;
+                if state.accepts_payment_mint(&old_mint) {
+                    state.remove_payment_mint(&old_mint)?;
+                }
+                if !state.accepts_payment_mint(&mint) {
+                    state.add_payment_mint(mint)?;
+                }
+                state.mint = mint;
+            }
+            GovernanceAction::SetRewardParams { params } => state.reward_params = params,
+            GovernanceAction::AddAttestor { attestor } => {
+                registry.add(attestor)?;
+                emit!(AttestorAdded {
+                    attestor,
+                    count: registry.attestors.len() as u8,
+                });
+            }
+            GovernanceAction::RemoveAttestor { attestor } => {
+                registry.remove(&attestor)?;
+                emit!(AttestorRemoved {
+                    attestor,
+                    count: registry.attestors.len() as u8,
+                });
+            }
+            GovernanceAction::SetAttestorThreshold { threshold } => {
+                registry.set_threshold(threshold)?;
+                emit!(AttestorThresholdUpdated { threshold });
+            }
+            GovernanceAction::SetTimelockDelay { delay_secs } => state.timelock_delay_secs = delay_secs,
+            GovernanceAction::AddPaymentMint { mint } => {
+                state.add_payment_mint(mint)?;
+                emit!(PaymentMintAdded { mint });
+            }
+            GovernanceAction::RemovePaymentMint { mint } => {
+                state.remove_payment_mint(&mint)?;
+                emit!(PaymentMintRemoved { mint });
+            }
+            // Approval only; `set_verifying_key` installs the key
+            GovernanceAction::SetVerifyingKey { .. } => {}
+        }
+        proposal.status = ProposalStatus::Executed;
+
+        emit!(ProposalExecuted {
+            proposal: proposal.key(),
+            id: proposal.id,
+            action: proposal.action,
+        });
         Ok(())
     }
 
-    pub fn set_attestor(ctx: Context<SetAttestor>, new_attestor: Pubkey) -> Result<()> {
-        require_keys_eq!(ctx.accounts.state.authority, ctx.accounts.authority.key(), DvpnError::Unauthorized);
-        ctx.accounts.state.attestor = new_attestor;
+    /// Drop a queued proposal (holder of the owning role, or admin)
+    pub fn cancel_proposal(ctx: Context<CancelProposal>) -> Result<()> {
+        let state = &ctx.accounts.state;
+        let proposal = &mut ctx.accounts.proposal;
+        let signer = ctx.accounts.authority.key();
+        require!(
+            signer == proposal.action.role_holder(state) || signer == state.admin,
+            DvpnError::Unauthorized
+        );
+        require!(proposal.status == ProposalStatus::Queued, DvpnError::ProposalNotQueued);
+        proposal.status = ProposalStatus::Cancelled;
+        emit!(ProposalCancelled {
+            proposal: proposal.key(),
+            id: proposal.id,
+            cancelled_by: signer,
+        });
         Ok(())
     }
 
-    pub fn set_mint(ctx: Context<SetMint>, new_mint: Pubkey) -> Result<()> {
-        require_keys_eq!(ctx.accounts.state.authority, ctx.accounts.authority.key(), DvpnError::Unauthorized);
-        ctx.accounts.state.mint = new_mint;
+    /// Create the attestor registry, seeded with `state.attestor` and a threshold of 1
+    pub fn initialize_attestor_registry(ctx: Context<InitializeAttestorRegistry>) -> Result<()> {
+        require_keys_eq!(ctx.accounts.state.attestor_manager, ctx.accounts.authority.key(), DvpnError::Unauthorized);
+        let registry = &mut ctx.accounts.attestor_registry;
+        registry.state = ctx.accounts.state.key();
+        registry.attestors = vec![ctx.accounts.state.attestor];
+        registry.threshold = 1;
+        registry.bump = ctx.bumps.attestor_registry;
         Ok(())
     }
 
+    /// Register the operator's node with `stake_lamports` of SOL stake. A node below
+    /// `min_stake_lamports` starts inactive; `stake_tokens` or `reactivate_node` activates it
+    /// once its total stake, including token stake credit, meets the minimum.
     pub fn register_node(ctx: Context<RegisterNode>, stake_lamports: u64, bandwidth_mbps: u32, meta_hash: [u8; 32], wg_pubkey: Option<[u8; 32]>) -> Result<()> {
+        require!(!ctx.accounts.state.paused, DvpnError::Paused);
+        let meets_min_stake = stake_lamports >= ctx.accounts.state.min_stake_lamports;
+
         // Save node key before mutable borrow
         let node_key = ctx.accounts.node.key();
         let operator_key = ctx.accounts.operator.key();
@@ -57,93 +408,317 @@ pub mod dvpn {
         node.wg_pubkey = wg_pubkey.unwrap_or([0; 32]);
         node.rating_sum = 0;
         node.rating_count = 0;
-        node.active = true;
+        node.active = meets_min_stake;
         node.registered_at = Clock::get()?.unix_timestamp;
+        node.last_heartbeat_ts = node.registered_at;
+        node.uptime_secs = 0;
+        node.marked_stale = false;
         node.last_slash_ts = 0;
+        node.zk_commitment = [0; 32];
+        node.open_sessions = 0;
+        node.unbonding_started_at = 0;
+        node.slash_count = 0;
+        node.slash_history = [SlashRecord::default(); SLASH_HISTORY_LEN];
+        node.pricing = PriceSchedule::default();
+        node.pricing_updated_at = 0;
+        node.paused = false;
+        node.commission_bps = 0;
+        node.unclaimed_epochs = 0;
+        node.delegated_lamports = 0;
+        node.delegation_shares = 0;
+        node.delegator_reward_per_share = 0;
+        node.staked_tokens = 0;
+        node.token_unbonding_amount = 0;
+        node.token_unbonding_started_at = 0;
+        node.slashed_tokens = 0;
+        node.metadata = NodeMetadata::default();
         Ok(())
     }
 
     pub fn record_usage(ctx: Context<RecordUsage>, bytes: u64) -> Result<()> {
-        // Only attestor can record usage for now; later ZK verification on-chain may authorize
-        require_keys_eq!(ctx.accounts.state.attestor, ctx.accounts.attestor.key(), DvpnError::Unauthorized);
+        require!(!ctx.accounts.state.paused, DvpnError::Paused);
+        require!(!ctx.accounts.node.paused, DvpnError::Paused);
+        // Needs a quorum of registered attestors: `attestor` plus co-signers in remaining accounts
+        require_signer_quorum(&ctx.accounts.attestor_registry, &ctx.accounts.attestor.key(), ctx.remaining_accounts)?;
+        let now = Clock::get()?.unix_timestamp;
+        let epoch = &mut ctx.accounts.epoch;
+        require!(now >= epoch.start_ts && now < epoch.end_ts, DvpnError::EpochNotActive);
+
         let node = &mut ctx.accounts.node;
         node.total_bytes_relayed = node
             .total_bytes_relayed
             .checked_add(bytes)
             .ok_or(DvpnError::MathOverflow)?;
-        // Accrue rewards as simple function of bytes and reward_rate_bps
-        // reward = bytes * rate_bps / 10_000, scaled to token smallest unit (assume 9 decimals; actual mint decimals read off-chain for claims)
-        // Here we simply store as raw units; mint happens on claim.
-        let add = bytes
-            .checked_mul(ctx.accounts.state.reward_rate_bps as u64)
-            .ok_or(DvpnError::MathOverflow)?
-            / 10_000u64;
-        node.unclaimed_reward = node
-            .unclaimed_reward
-            .checked_add(add)
+
+        // Rewards are paid out of the epoch budget pro-rata to weighted bytes once the epoch ends
+        let multiplier = ctx.accounts.state.reward_params.multiplier(node, ctx.accounts.state.token_stake_rate, now);
+        let weighted = weighted_bytes(bytes, multiplier.total_bps);
+        let node_epoch = &mut ctx.accounts.node_epoch;
+        if node_epoch.node == Pubkey::default() {
+            node_epoch.node = node.key();
+            node_epoch.epoch = epoch.id;
+            node_epoch.bump = ctx.bumps.node_epoch;
+            node.unclaimed_epochs = node.unclaimed_epochs.checked_add(1).ok_or(DvpnError::MathOverflow)?;
+        }
+        node_epoch.bytes = node_epoch.bytes.checked_add(bytes).ok_or(DvpnError::MathOverflow)?;
+        node_epoch.weighted_bytes = node_epoch.weighted_bytes.checked_add(weighted).ok_or(DvpnError::MathOverflow)?;
+        epoch.total_bytes = epoch.total_bytes.checked_add(bytes).ok_or(DvpnError::MathOverflow)?;
+        epoch.total_weighted_bytes = epoch
+            .total_weighted_bytes
+            .checked_add(weighted)
             .ok_or(DvpnError::MathOverflow)?;
         Ok(())
     }
 
+    /// Read-only: the reward multiplier `record_usage` would apply to this node right now and
+    /// what `bytes` would count for. Call via simulation and read the return data.
+    pub fn simulate_rewards(ctx: Context<SimulateRewards>, bytes: u64) -> Result<RewardSimulation> {
+        let now = Clock::get()?.unix_timestamp;
+        let multiplier = ctx.accounts.state.reward_params.multiplier(&ctx.accounts.node, ctx.accounts.state.token_stake_rate, now);
+        Ok(RewardSimulation {
+            multiplier,
+            bytes,
+            weighted_bytes: weighted_bytes(bytes, multiplier.total_bps),
+        })
+    }
+
+    /// Open the next reward epoch (admin only). `emission_tokens` is in whole tokens and
+    /// scaled by the mint's decimals; the sum of all budgets may not exceed `max_supply`.
+    pub fn start_epoch(ctx: Context<StartEpoch>, start_ts: i64, end_ts: i64, emission_tokens: u64) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+        require_keys_eq!(state.admin, ctx.accounts.admin.key(), DvpnError::Unauthorized);
+        require!(end_ts > start_ts && start_ts >= state.last_epoch_end, DvpnError::InvalidEpoch);
+
+        let budget = token_amount(emission_tokens, ctx.accounts.dvpn_mint.decimals)?;
+        let allocated = state.emission_allocated.checked_add(budget).ok_or(DvpnError::MathOverflow)?;
+        require!(allocated <= state.max_supply, DvpnError::SupplyCapExceeded);
+
+        let epoch = &mut ctx.accounts.epoch;
+        epoch.state = state.key();
+        epoch.id = state.next_epoch_id;
+        epoch.start_ts = start_ts;
+        epoch.end_ts = end_ts;
+        epoch.emission_budget = budget;
+        epoch.total_bytes = 0;
+        epoch.total_weighted_bytes = 0;
+        epoch.bump = ctx.bumps.epoch;
+
+        state.emission_allocated = allocated;
+        state.last_epoch_end = end_ts;
+        state.next_epoch_id = state.next_epoch_id.checked_add(1).ok_or(DvpnError::MathOverflow)?;
+
+        emit!(EpochStarted {
+            epoch: epoch.key(),
+            id: epoch.id,
+            start_ts,
+            end_ts,
+            emission_budget: budget,
+        });
+        Ok(())
+    }
+
+    /// Mint the node's share of every finalized epoch passed in remaining accounts as
+    /// `(epoch, node_epoch)` pairs, plus any reward accrued before epochs existed.
     pub fn claim_rewards(ctx: Context<ClaimRewards>) -> Result<()> {
-        let amount = ctx.accounts.node.unclaimed_reward;
-        require!(amount > 0, DvpnError::NothingToClaim);
-        ctx.accounts.node.unclaimed_reward = 0;
+        require!(!ctx.accounts.state.paused, DvpnError::Paused);
+        require!(!ctx.accounts.node.paused, DvpnError::Paused);
+        require!(ctx.remaining_accounts.len() % 2 == 0, DvpnError::InvalidEpoch);
 
-        let cpi_accounts = MintTo {
-            mint: ctx.accounts.dvpn_mint.to_account_info(),
-            to: ctx.accounts.operator_token_account.to_account_info(),
-            authority: ctx.accounts.state_signer.to_account_info(),
-        };
-        let seeds: &[&[u8]] = &[b"state", ctx.accounts.state.authority.as_ref(), &[ctx.accounts.state.bump]];
-        let signer = &[&seeds[..]];
-        let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), cpi_accounts, signer);
-        token::mint_to(cpi_ctx, amount)?;
+        let now = Clock::get()?.unix_timestamp;
+        let state_key = ctx.accounts.state.key();
+        let node_key = ctx.accounts.node.key();
+        // Epoch shares come out of budgets already counted in `emission_allocated`; legacy
+        // rewards were never budgeted, so they are allocated against `max_supply` here
+        let legacy = ctx.accounts.node.unclaimed_reward;
+        if legacy > 0 {
+            let state = &mut ctx.accounts.state;
+            state.emission_allocated = state.emission_allocated.checked_add(legacy).ok_or(DvpnError::MathOverflow)?;
+            require!(state.emission_allocated <= state.max_supply, DvpnError::SupplyCapExceeded);
+        }
+        let mut amount = legacy;
+        for pair in ctx.remaining_accounts.chunks(2) {
+            let share = claim_epoch_share(&state_key, &node_key, &pair[0], &pair[1], now)?;
+            amount = amount.checked_add(share).ok_or(DvpnError::MathOverflow)?;
+        }
+        require!(amount > 0 || !ctx.remaining_accounts.is_empty(), DvpnError::NothingToClaim);
+        let node = &mut ctx.accounts.node;
+        node.unclaimed_reward = 0;
+        node.unclaimed_epochs = node
+            .unclaimed_epochs
+            .checked_sub((ctx.remaining_accounts.len() / 2) as u32)
+            .ok_or(DvpnError::MathOverflow)?;
+        // Delegators' part is credited to the node's accumulator and minted by `claim_delegator_rewards`
+        let token_stake_rate = ctx.accounts.state.token_stake_rate;
+        let (operator_amount, delegator_amount) = ctx.accounts.node.distribute_reward(amount, token_stake_rate)?;
+
+        if operator_amount > 0 {
+            let cpi_accounts = MintTo {
+                mint: ctx.accounts.dvpn_mint.to_account_info(),
+                to: ctx.accounts.operator_token_account.to_account_info(),
+                authority: ctx.accounts.state_signer.to_account_info(),
+            };
+            let seeds: &[&[u8]] = &[b"state", ctx.accounts.state.authority.as_ref(), &[ctx.accounts.state.bump]];
+            let signer = &[seeds];
+            let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), cpi_accounts, signer);
+            token_interface::mint_to(cpi_ctx, operator_amount)?;
+        }
+        emit!(RewardsClaimed {
+            node: node_key,
+            amount: operator_amount,
+            epochs: (ctx.remaining_accounts.len() / 2) as u32,
+            delegator_amount,
+        });
         Ok(())
     }
 
     /// Faucet: Mint tokens to user for testing (devnet only)
     pub fn faucet(ctx: Context<Faucet>, amount: u64) -> Result<()> {
-        // Only attestor can call faucet
-        require_keys_eq!(ctx.accounts.state.attestor, ctx.accounts.attestor.key(), DvpnError::Unauthorized);
-        
+        require!(!ctx.accounts.state.paused, DvpnError::Paused);
+        // Only an attestor quorum can call faucet
+        require_signer_quorum(&ctx.accounts.attestor_registry, &ctx.accounts.attestor.key(), ctx.remaining_accounts)?;
+        // Drawn from the same allowance as epoch budgets so it can't eat into rewards already promised
+        let state = &mut ctx.accounts.state;
+        state.emission_allocated = state.emission_allocated.checked_add(amount).ok_or(DvpnError::MathOverflow)?;
+        require!(state.emission_allocated <= state.max_supply, DvpnError::SupplyCapExceeded);
+
         let cpi_accounts = MintTo {
             mint: ctx.accounts.dvpn_mint.to_account_info(),
             to: ctx.accounts.user_token_account.to_account_info(),
             authority: ctx.accounts.state_signer.to_account_info(),
         };
         let seeds: &[&[u8]] = &[b"state", ctx.accounts.state.authority.as_ref(), &[ctx.accounts.state.bump]];
-        let signer = &[&seeds[..]];
+        let signer = &[seeds];
         let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), cpi_accounts, signer);
-        token::mint_to(cpi_ctx, amount)?;
+        token_interface::mint_to(cpi_ctx, amount)?;
         Ok(())
     }
 
     /// Start a new VPN session with escrow deposit
-    pub fn start_session(ctx: Context<StartSession>, deposit_amount: u64) -> Result<()> {
+    pub fn start_session(ctx: Context<StartSession>, deposit_amount: u64, max_duration: i64) -> Result<()> {
+        require!(!ctx.accounts.state.paused, DvpnError::Paused);
+        require!(!ctx.accounts.node.paused, DvpnError::Paused);
+        require!(deposit_amount > 0, DvpnError::InvalidDeposit);
+        require!(
+            max_duration > 0 && max_duration <= MAX_SESSION_DURATION_SECS,
+            DvpnError::InvalidDuration
+        );
+        require!(ctx.accounts.node.active, DvpnError::NodeInactive);
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            !ctx.accounts.node.is_stale(now, ctx.accounts.state.heartbeat_staleness_secs),
+            DvpnError::NodeStale
+        );
+        let payment_mint = ctx.accounts.payment_mint.key();
+        let payment_rate = ctx
+            .accounts
+            .node
+            .payment_rate(&payment_mint, &ctx.accounts.state.mint)
+            .ok_or(DvpnError::NoPaymentRate)?;
+        let pricing = ctx.accounts.node.pricing.convert(payment_rate)?;
+        let max_sessions = ctx.accounts.node.metadata.max_sessions;
+        require!(
+            max_sessions == 0 || ctx.accounts.node.open_sessions < max_sessions,
+            DvpnError::NodeAtCapacity
+        );
+
+        // Move the deposit into the session-owned escrow. Under a Token-2022 transfer fee the
+        // escrow receives less than was sent, and only what it received can be paid out.
+        let deposit = transfer_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.payment_mint,
+            ctx.accounts.user_token_account.to_account_info(),
+            ctx.accounts.escrow_token_account.to_account_info(),
+            ctx.accounts.user.to_account_info(),
+            &[],
+            deposit_amount,
+        )?;
+        require!(deposit > 0, DvpnError::InvalidDeposit);
+
+        let node = &mut ctx.accounts.node;
+        node.open_sessions = node.open_sessions
+            .checked_add(1)
+            .ok_or(DvpnError::MathOverflow)?;
+
+        let counter = &mut ctx.accounts.session_counter;
+        let session_id = counter.next_session_id;
+        counter.user = ctx.accounts.user.key();
+        counter.next_session_id = session_id
+            .checked_add(1)
+            .ok_or(DvpnError::MathOverflow)?;
+        counter.bump = ctx.bumps.session_counter;
+
         let session = &mut ctx.accounts.session;
+        session.session_id = session_id;
         session.user = ctx.accounts.user.key();
         session.node = ctx.accounts.node.key();
-        session.deposit_amount = deposit_amount;
+        session.deposit_amount = deposit;
         session.bytes_used = 0;
-        session.started_at = Clock::get()?.unix_timestamp;
-        session.closed = false;
+        let now = Clock::get()?.unix_timestamp;
+        session.started_at = now;
+        session.max_duration = max_duration;
+        session.expires_at = now.checked_add(max_duration).ok_or(DvpnError::MathOverflow)?;
+        session.rating = 0;
+        session.status = SessionStatus::Active;
+        session.ended_at = 0;
+        session.dispute_deadline = 0;
+        session.disputed_bytes = 0;
+        session.dispute_bond = 0;
         session.bump = ctx.bumps.session;
+        session.attestation_nonce = 0;
+        session.pricing = pricing;
+        session.payment_mint = payment_mint;
+        session.payment_rate = payment_rate;
         
         emit!(SessionStarted {
             session: session.key(),
+            session_id,
             user: session.user,
             node: session.node,
-            deposit: deposit_amount,
+            deposit,
+            expires_at: session.expires_at,
+            payment_mint,
+        });
+        Ok(())
+    }
+
+    /// Add tokens to an open session's escrow so it can keep running without re-settling
+    pub fn top_up_session(ctx: Context<TopUpSession>, amount: u64) -> Result<()> {
+        require!(!ctx.accounts.state.paused, DvpnError::Paused);
+        require!(amount > 0, DvpnError::InvalidDeposit);
+        let session = &ctx.accounts.session;
+        require!(session.status == SessionStatus::Active, DvpnError::SessionClosed);
+        require!(Clock::get()?.unix_timestamp <= session.expires_at, DvpnError::SessionExpired);
+
+        let amount = transfer_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.payment_mint,
+            ctx.accounts.user_token_account.to_account_info(),
+            ctx.accounts.escrow_token_account.to_account_info(),
+            ctx.accounts.user.to_account_info(),
+            &[],
+            amount,
+        )?;
+
+        let session = &mut ctx.accounts.session;
+        session.deposit_amount = session.deposit_amount
+            .checked_add(amount)
+            .ok_or(DvpnError::MathOverflow)?;
+
+        emit!(SessionToppedUp {
+            session: session.key(),
+            amount,
+            deposit: session.deposit_amount,
         });
         Ok(())
     }
 
-    /// Submit bandwidth usage for a session (attestor only)
+    /// Submit bandwidth usage for a session (attestor quorum only)
     pub fn submit_usage(ctx: Context<SubmitUsage>, bytes: u64) -> Result<()> {
-        require_keys_eq!(ctx.accounts.state.attestor, ctx.accounts.attestor.key(), DvpnError::Unauthorized);
+        require!(!ctx.accounts.state.paused, DvpnError::Paused);
+        require_signer_quorum(&ctx.accounts.attestor_registry, &ctx.accounts.attestor.key(), ctx.remaining_accounts)?;
         let session = &mut ctx.accounts.session;
-        require!(!session.closed, DvpnError::SessionClosed);
+        require!(session.status == SessionStatus::Active, DvpnError::SessionClosed);
+        require!(Clock::get()?.unix_timestamp <= session.expires_at, DvpnError::SessionExpired);
         
         session.bytes_used = session.bytes_used
             .checked_add(bytes)
@@ -156,395 +731,3826 @@ pub mod dvpn {
         Ok(())
     }
 
-    /// Settle session and transfer tokens from escrow to node operator
-    pub fn settle_session(ctx: Context<SettleSession>) -> Result<()> {
+    /// Stop usage on a session and open its dispute window (session user, node operator or
+    /// attestor). The escrow is paid out by `finalize_session` once `state.dispute_window_secs`
+    /// passes without a dispute.
+    pub fn settle_session(ctx: Context<RequestSettlement>) -> Result<()> {
+        require!(!ctx.accounts.state.paused, DvpnError::Paused);
+        require!(!ctx.accounts.node.paused, DvpnError::Paused);
         let session = &mut ctx.accounts.session;
-        require!(!session.closed, DvpnError::SessionClosed);
+        require!(session.status == SessionStatus::Active, DvpnError::SessionClosed);
+        open_dispute_window(session, &ctx.accounts.state, Clock::get()?.unix_timestamp)
+    }
 
-        let node = &ctx.accounts.node;
-        let price_per_mb = node.bandwidth_mbps as u64; // simplified pricing
-        
-        // Calculate payout: (bytes_used / 1_048_576) * price_per_mb
-        let payout = (session.bytes_used as u128)
-            .checked_mul(price_per_mb as u128)
-            .ok_or(DvpnError::MathOverflow)?
-            / 1_048_576u128;
-        
-        let payout_u64 = payout.min(session.deposit_amount as u128) as u64;
-        
-        // Protocol fee (1%)
-        let protocol_fee = payout_u64 / 100;
-        let node_amount = payout_u64.saturating_sub(protocol_fee);
+    /// Pay out a pending session whose dispute window has passed (permissionless)
+    pub fn finalize_session(ctx: Context<SettleSession>) -> Result<()> {
+        require!(!ctx.accounts.state.paused, DvpnError::Paused);
+        require!(!ctx.accounts.node.paused, DvpnError::Paused);
+        let session = &ctx.accounts.session;
+        require!(session.status == SessionStatus::Pending, DvpnError::SessionNotPending);
+        require!(Clock::get()?.unix_timestamp > session.dispute_deadline, DvpnError::DisputeWindowOpen);
 
-        // Store values before closing to avoid borrow conflicts
-        let session_key = session.key();
-        let bytes_used = session.bytes_used;
-        let user_key = session.user;
-        let node_key = session.node;
-        let bump = session.bump;
+        // Price comes from the schedule snapshotted at start_session
+        let payout = SessionPayout::compute(session, session.bytes_used, ctx.accounts.state.effective_fee_bps())?;
+        let accounts = &ctx.accounts;
+        disburse_session(
+            &accounts.token_program,
+            &accounts.payment_mint,
+            &accounts.escrow_token_account,
+            &accounts.session,
+            &accounts.node_token_account,
+            &accounts.user_token_account,
+            accounts.treasury_token_account.as_ref(),
+            &payout,
+        )?;
 
-        // Mark session as closed
-        session.closed = true;
+        let accounts = &mut *ctx.accounts;
+        record_settlement(&mut accounts.session, &mut accounts.node, &mut accounts.treasury, &accounts.state, &payout)?;
+        emit!(SessionSettled {
+            session: accounts.session.key(),
+            payout: payout.node_amount,
+            protocol_fee: payout.protocol_fee,
+            refund: payout.refund,
+            bytes: accounts.session.bytes_used,
+        });
+        Ok(())
+    }
 
-        // Transfer tokens from escrow PDA to node operator's token account
-        let seeds: &[&[u8]] = &[
-            b"escrow",
-            user_key.as_ref(),
-            node_key.as_ref(),
-            &[bump]
-        ];
-        let signer = &[&seeds[..]];
-        
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.escrow_token_account.to_account_info(),
-            to: ctx.accounts.node_token_account.to_account_info(),
-            authority: ctx.accounts.session.to_account_info(),
-        };
-        let cpi_ctx = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            cpi_accounts,
-            signer
+    /// Contest the usage of a pending session with the user's own byte count, posting
+    /// `state.dispute_bond` into escrow. Resolved by `settle_session_with_attestation` or
+    /// `settle_session_with_proof` within `DISPUTE_RESOLUTION_SECS`, after which `expire_dispute`
+    /// settles it. Not subject to pause so the window can't be run out.
+    pub fn dispute_session(ctx: Context<DisputeSession>, user_bytes: u64) -> Result<()> {
+        let state = &ctx.accounts.state;
+        require!(
+            state.dispute_bond > 0 && state.dispute_slash_lamports > 0,
+            DvpnError::DisputesNotConfigured
         );
-        token::transfer(cpi_ctx, node_amount)?;
-        
+        let now = Clock::get()?.unix_timestamp;
+        let session = &ctx.accounts.session;
+        require!(session.status == SessionStatus::Pending, DvpnError::SessionNotPending);
+        require!(now <= session.dispute_deadline, DvpnError::DisputeWindowClosed);
+        require!(user_bytes < session.bytes_used, DvpnError::InvalidDispute);
+
+        // The bond is quoted in `state.mint`; convert it at the session's rate and record
+        // what the escrow actually received, net of any transfer fee
+        let bond = convert_amount(state.dispute_bond, session.payment_rate)?;
+        require!(bond > 0, DvpnError::DisputesNotConfigured);
+        let bond = transfer_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.payment_mint,
+            ctx.accounts.user_token_account.to_account_info(),
+            ctx.accounts.escrow_token_account.to_account_info(),
+            ctx.accounts.user.to_account_info(),
+            &[],
+            bond,
+        )?;
+
+        let session = &mut ctx.accounts.session;
+        session.status = SessionStatus::Disputed;
+        session.disputed_bytes = user_bytes;
+        session.dispute_bond = bond;
+        session.dispute_deadline = now.checked_add(DISPUTE_RESOLUTION_SECS).ok_or(DvpnError::MathOverflow)?;
+        emit!(SessionDisputed {
+            session: session.key(),
+            node_bytes: session.bytes_used,
+            user_bytes,
+            bond,
+        });
+        Ok(())
+    }
+
+    /// Settle a dispute that no attestor quorum or proof resolved by its deadline (permissionless).
+    /// Without a verdict the node's submitted usage stands, as for an undisputed session, the
+    /// bond goes back to the user and nobody is slashed.
+    pub fn expire_dispute(ctx: Context<SettleSession>) -> Result<()> {
+        require!(!ctx.accounts.state.paused, DvpnError::Paused);
+        require!(!ctx.accounts.node.paused, DvpnError::Paused);
+        let session = &ctx.accounts.session;
+        require!(session.status == SessionStatus::Disputed, DvpnError::SessionNotDisputed);
+        require!(Clock::get()?.unix_timestamp > session.dispute_deadline, DvpnError::DisputeResolutionOpen);
+
+        let mut payout = SessionPayout::compute(session, session.bytes_used, ctx.accounts.state.effective_fee_bps())?;
+        payout.refund = payout.refund.checked_add(session.dispute_bond).ok_or(DvpnError::MathOverflow)?;
+        let accounts = &ctx.accounts;
+        disburse_session(
+            &accounts.token_program,
+            &accounts.payment_mint,
+            &accounts.escrow_token_account,
+            &accounts.session,
+            &accounts.node_token_account,
+            &accounts.user_token_account,
+            accounts.treasury_token_account.as_ref(),
+            &payout,
+        )?;
+
+        let accounts = &mut *ctx.accounts;
+        record_settlement(&mut accounts.session, &mut accounts.node, &mut accounts.treasury, &accounts.state, &payout)?;
+        let session = &accounts.session;
+        emit!(DisputeExpired {
+            session: session.key(),
+            node_bytes: session.bytes_used,
+            user_bytes: session.disputed_bytes,
+            bond: session.dispute_bond,
+        });
         emit!(SessionSettled {
-            session: session_key,
-            payout: node_amount,
-            bytes: bytes_used,
+            session: session.key(),
+            payout: payout.node_amount,
+            protocol_fee: payout.protocol_fee,
+            refund: payout.refund,
+            bytes: session.bytes_used,
         });
         Ok(())
     }
 
-    /// Settle session with ZK proof attestation
+    /// Settle session with attestor signatures.
+    /// Earlier instructions in the transaction must be Ed25519 program instructions in
+    /// which at least `threshold` registered attestors sign `attestation_message(..)`.
+    /// Also resolves a disputed session.
     pub fn settle_session_with_attestation(
         ctx: Context<SettleWithAttestation>,
         total_bytes: u64,
-        attestor_pubkey: Pubkey,
-        _attestor_sig: Vec<u8>,
+        nonce: u64,
+        expires_at: i64,
     ) -> Result<()> {
-        let session = &mut ctx.accounts.session;
-        require!(!session.closed, DvpnError::SessionClosed);
+        require!(!ctx.accounts.state.paused, DvpnError::Paused);
+        require!(!ctx.accounts.node.paused, DvpnError::Paused);
+        let session_key = ctx.accounts.session.key();
+        let session = &ctx.accounts.session;
+        require!(session.accepts_verified_usage(), DvpnError::SessionClosed);
+        let now = Clock::get()?.unix_timestamp;
+        require!(now <= expires_at, DvpnError::AttestationExpired);
+        require!(nonce > session.attestation_nonce, DvpnError::AttestationReplayed);
 
-        // Verify attestor matches state
-        require_keys_eq!(ctx.accounts.state.attestor, attestor_pubkey, DvpnError::Unauthorized);
+        // Verify a quorum of attestors signed exactly this settlement
+        let message = attestation_message(&session_key, &session.user, &session.node, total_bytes, nonce, expires_at);
+        let registry = &ctx.accounts.attestor_registry;
+        let attestors = ed25519_approvals(&ctx.accounts.instructions.to_account_info(), registry, &message)?;
+        require!(attestors.len() >= registry.threshold as usize, DvpnError::InsufficientApprovals);
 
-        // Update session bytes
-        session.bytes_used = total_bytes;
+        let accounts = &mut *ctx.accounts;
+        accounts.session.attestation_nonce = nonce;
+        let payout = apply_verified_usage(
+            &mut accounts.session,
+            &mut accounts.node,
+            &mut accounts.treasury,
+            &accounts.state,
+            total_bytes,
+            now,
+        )?;
+        let accounts = &ctx.accounts;
+        disburse_session(
+            &accounts.token_program,
+            &accounts.payment_mint,
+            &accounts.escrow_token_account,
+            &accounts.session,
+            &accounts.node_token_account,
+            &accounts.user_token_account,
+            accounts.treasury_token_account.as_ref(),
+            &payout,
+        )?;
 
-        let node = &ctx.accounts.node;
-        let price_per_mb = node.bandwidth_mbps as u64;
-        
-        // Calculate payout
-        let payout = (total_bytes as u128)
-            .checked_mul(price_per_mb as u128)
-            .ok_or(DvpnError::MathOverflow)?
-            / 1_048_576u128;
-        
-        let payout_u64 = payout.min(session.deposit_amount as u128) as u64;
-        let protocol_fee = payout_u64 / 100;
-        let node_amount = payout_u64.saturating_sub(protocol_fee);
+        emit!(SessionSettled {
+            session: session_key,
+            payout: payout.node_amount,
+            protocol_fee: payout.protocol_fee,
+            refund: payout.refund,
+            bytes: total_bytes,
+        });
+        emit!(AttestationVerified {
+            session: session_key,
+            attestors,
+            total_bytes,
+            nonce,
+        });
+        Ok(())
+    }
+
+    /// Payment-channel settlement of an active session by the node operator. The instruction
+    /// immediately before this one must be an Ed25519 program instruction in which the
+    /// session user signs `receipt_message(session, cumulative_bytes, expires_at)`. Users sign
+    /// a new short-lived receipt as usage grows; the node only ever presents the latest. The
+    /// user agreed to the count, so there is no dispute window. Pending sessions go through
+    /// `finalize_session` and disputed ones through attestors, so no dispute bond is ever
+    /// held when a receipt settles.
+    pub fn settle_session_with_receipt(
+        ctx: Context<SettleWithReceipt>,
+        cumulative_bytes: u64,
+        expires_at: i64,
+    ) -> Result<()> {
+        require!(!ctx.accounts.state.paused, DvpnError::Paused);
+        require!(!ctx.accounts.node.paused, DvpnError::Paused);
+        let session_key = ctx.accounts.session.key();
+        let session = &ctx.accounts.session;
+        require!(session.status == SessionStatus::Active, DvpnError::SessionClosed);
+        let now = Clock::get()?.unix_timestamp;
+        require!(now <= expires_at, DvpnError::ReceiptExpired);
+
+        let ix_sysvar = ctx.accounts.instructions.to_account_info();
+        let current = load_current_index_checked(&ix_sysvar)? as usize;
+        require!(current > 0, DvpnError::MissingEd25519Instruction);
+        let receipt_ix = load_instruction_at_checked(current - 1, &ix_sysvar)?;
+        verify_ed25519_ix(
+            &receipt_ix,
+            &session.user,
+            &receipt_message(&session_key, cumulative_bytes, expires_at),
+        )?;
+
+        let accounts = &mut *ctx.accounts;
+        let payout = apply_verified_usage(
+            &mut accounts.session,
+            &mut accounts.node,
+            &mut accounts.treasury,
+            &accounts.state,
+            cumulative_bytes,
+            now,
+        )?;
+        let accounts = &ctx.accounts;
+        disburse_session(
+            &accounts.token_program,
+            &accounts.payment_mint,
+            &accounts.escrow_token_account,
+            &accounts.session,
+            &accounts.node_token_account,
+            &accounts.user_token_account,
+            accounts.treasury_token_account.as_ref(),
+            &payout,
+        )?;
+
+        emit!(SessionSettled {
+            session: session_key,
+            payout: payout.node_amount,
+            protocol_fee: payout.protocol_fee,
+            refund: payout.refund,
+            bytes: cumulative_bytes,
+        });
+        emit!(ReceiptSettled {
+            session: session_key,
+            cumulative_bytes,
+        });
+        Ok(())
+    }
+
+    /// Store the Groth16 verifying key for bandwidth proofs (admin only). The key must have
+    /// been approved through an executed `SetVerifyingKey` proposal carrying its
+    /// `verifying_key_hash`; each proposal installs one key.
+    /// G2 coordinates use the EIP-197 encoding expected by the alt_bn128 syscalls.
+    pub fn set_verifying_key(
+        ctx: Context<SetVerifyingKey>,
+        alpha_g1: [u8; 64],
+        beta_g2: [u8; 128],
+        gamma_g2: [u8; 128],
+        delta_g2: [u8; 128],
+        ic: [[u8; 64]; VerifyingKey::IC_LEN],
+    ) -> Result<()> {
+        require_keys_eq!(ctx.accounts.state.admin, ctx.accounts.authority.key(), DvpnError::Unauthorized);
+        let proposal = &mut ctx.accounts.proposal;
+        require!(proposal.status == ProposalStatus::Executed, DvpnError::ProposalNotExecuted);
+        let vk_hash = verifying_key_hash(&alpha_g1, &beta_g2, &gamma_g2, &delta_g2, &ic);
+        require!(
+            proposal.action == GovernanceAction::SetVerifyingKey { vk_hash },
+            DvpnError::VerifyingKeyNotApproved
+        );
+        proposal.status = ProposalStatus::Applied;
+
+        let vk = &mut ctx.accounts.verifying_key;
+        vk.alpha_g1 = alpha_g1;
+        vk.beta_g2 = beta_g2;
+        vk.gamma_g2 = gamma_g2;
+        vk.delta_g2 = delta_g2;
+        vk.ic = ic;
+        vk.bump = ctx.bumps.verifying_key;
+        emit!(VerifyingKeyUpdated {
+            proposal: ctx.accounts.proposal.key(),
+            vk_hash,
+        });
+        Ok(())
+    }
+
+    /// Set the commitment a node's bandwidth proofs are bound to, `Poseidon(node_secret)` (operator only)
+    pub fn set_node_commitment(ctx: Context<SetNodeCommitment>, commitment: [u8; 32]) -> Result<()> {
+        require!(is_scalar_field_element(&commitment), DvpnError::InvalidProof);
+        ctx.accounts.node.zk_commitment = commitment;
+        Ok(())
+    }
+
+    /// Settle an active or disputed session with a Groth16 proof over (total, session nonce,
+    /// node commitment, usage commitment) (node operator only). The instruction immediately
+    /// before this one must be an Ed25519 program instruction in which the session user
+    /// signs `usage_message(session, usage_commitment)`, so the proven intervals are ones
+    /// the user agreed to and the proven total can decide a dispute.
+    pub fn settle_session_with_proof(
+        ctx: Context<SettleWithProof>,
+        total_bytes: u64,
+        usage_commitment: [u8; 32],
+        proof_a: [u8; 64],
+        proof_b: [u8; 128],
+        proof_c: [u8; 64],
+    ) -> Result<()> {
+        require!(!ctx.accounts.state.paused, DvpnError::Paused);
+        require!(!ctx.accounts.node.paused, DvpnError::Paused);
+        let session_key = ctx.accounts.session.key();
+        require!(ctx.accounts.session.accepts_verified_usage(), DvpnError::SessionClosed);
+        require!(is_scalar_field_element(&usage_commitment), DvpnError::InvalidProof);
+
+        let ix_sysvar = ctx.accounts.instructions.to_account_info();
+        let current = load_current_index_checked(&ix_sysvar)? as usize;
+        require!(current > 0, DvpnError::MissingEd25519Instruction);
+        let usage_ix = load_instruction_at_checked(current - 1, &ix_sysvar)?;
+        verify_ed25519_ix(
+            &usage_ix,
+            &ctx.accounts.session.user,
+            &usage_message(&session_key, &usage_commitment),
+        )?;
+
+        let mut total_input = [0u8; 32];
+        total_input[24..].copy_from_slice(&total_bytes.to_be_bytes());
+        let public_inputs = [
+            total_input,
+            session_nonce_input(&session_key),
+            ctx.accounts.node.zk_commitment,
+            usage_commitment,
+        ];
+        groth16_verify(&ctx.accounts.verifying_key, &proof_a, &proof_b, &proof_c, &public_inputs)?;
+
+        let now = Clock::get()?.unix_timestamp;
+        let accounts = &mut *ctx.accounts;
+        let payout = apply_verified_usage(
+            &mut accounts.session,
+            &mut accounts.node,
+            &mut accounts.treasury,
+            &accounts.state,
+            total_bytes,
+            now,
+        )?;
+        let accounts = &ctx.accounts;
+        disburse_session(
+            &accounts.token_program,
+            &accounts.payment_mint,
+            &accounts.escrow_token_account,
+            &accounts.session,
+            &accounts.node_token_account,
+            &accounts.user_token_account,
+            accounts.treasury_token_account.as_ref(),
+            &payout,
+        )?;
+
+        emit!(SessionSettled {
+            session: session_key,
+            payout: payout.node_amount,
+            protocol_fee: payout.protocol_fee,
+            refund: payout.refund,
+            bytes: total_bytes,
+        });
+        emit!(ProofVerified {
+            session: session_key,
+            total_bytes,
+        });
+        Ok(())
+    }
+
+    /// Cancel an expired session that never received usage and refund the full deposit (user only).
+    /// Not subject to pause so deposits are never locked in escrow.
+    pub fn cancel_session(ctx: Context<CancelSession>) -> Result<()> {
+        let session = &mut ctx.accounts.session;
+        require!(session.status == SessionStatus::Active, DvpnError::SessionClosed);
+        require!(Clock::get()?.unix_timestamp > session.expires_at, DvpnError::SessionNotExpired);
+        require!(session.bytes_used == 0, DvpnError::UsageSubmitted);
 
-        // Store values before closing to avoid borrow conflicts
         let session_key = session.key();
+        let refund = session.deposit_amount;
         let user_key = session.user;
         let node_key = session.node;
+        let session_id = session.session_id.to_le_bytes();
         let bump = session.bump;
 
-        // Mark session as closed
-        session.closed = true;
+        session.status = SessionStatus::Cancelled;
+        let node = &mut ctx.accounts.node;
+        node.open_sessions = node.open_sessions.saturating_sub(1);
 
-        // Transfer tokens
         let seeds: &[&[u8]] = &[
-            b"escrow",
+            b"session",
             user_key.as_ref(),
             node_key.as_ref(),
+            &session_id,
             &[bump]
         ];
-        let signer = &[&seeds[..]];
-        
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.escrow_token_account.to_account_info(),
-            to: ctx.accounts.node_token_account.to_account_info(),
-            authority: ctx.accounts.session.to_account_info(),
-        };
-        let cpi_ctx = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            cpi_accounts,
-            signer
-        );
-        token::transfer(cpi_ctx, node_amount)?;
-        
-        emit!(SessionSettled {
+        let accounts = &ctx.accounts;
+        escrow_transfer(&accounts.token_program, &accounts.payment_mint, &accounts.escrow_token_account, &accounts.user_token_account, &accounts.session, seeds, refund)?;
+
+        emit!(SessionCancelled {
             session: session_key,
-            payout: node_amount,
-            bytes: total_bytes,
+            refund,
         });
         Ok(())
     }
 
-    /// Slash node for misbehavior
-    pub fn slash_node(ctx: Context<SlashNode>, amount: u64, _reason: String) -> Result<()> {
-        require_keys_eq!(ctx.accounts.state.attestor, ctx.accounts.authority.key(), DvpnError::Unauthorized);
-        
+    /// Permissionless crank: move an expired session into its dispute window on its
+    /// last submitted usage
+    pub fn force_settle_expired(ctx: Context<ForceSettleExpired>) -> Result<()> {
+        require!(!ctx.accounts.state.paused, DvpnError::Paused);
+        require!(!ctx.accounts.node.paused, DvpnError::Paused);
+        let now = Clock::get()?.unix_timestamp;
+        let session = &mut ctx.accounts.session;
+        require!(session.status == SessionStatus::Active, DvpnError::SessionClosed);
+        require!(now > session.expires_at, DvpnError::SessionNotExpired);
+        emit!(SessionExpired {
+            session: session.key(),
+            bytes: session.bytes_used,
+            settled_by: ctx.accounts.authority.key(),
+        });
+        open_dispute_window(session, &ctx.accounts.state, now)
+    }
+
+    /// Rate the node of a settled session, 1 to 5 stars (user only, once)
+    pub fn rate_node(ctx: Context<RateNode>, rating: u8) -> Result<()> {
+        require!(!ctx.accounts.state.paused, DvpnError::Paused);
+        require!((MIN_RATING..=MAX_RATING).contains(&rating), DvpnError::InvalidRating);
+        let session = &mut ctx.accounts.session;
+        require!(session.status == SessionStatus::Settled, DvpnError::SessionNotSettled);
+        require!(session.rating == 0, DvpnError::AlreadyRated);
+        session.rating = rating;
+
         let node = &mut ctx.accounts.node;
-        if node.stake_lamports < amount {
-            node.stake_lamports = 0;
-        } else {
-            node.stake_lamports = node.stake_lamports
-                .checked_sub(amount)
-                .ok_or(DvpnError::MathOverflow)?;
-        }
-        node.last_slash_ts = Clock::get()?.unix_timestamp;
-        
-        emit!(NodeSlashed { 
-            node: node.key(), 
-            amount,
+        node.rating_sum = node.rating_sum.checked_add(rating as u64).ok_or(DvpnError::MathOverflow)?;
+        node.rating_count = node.rating_count.checked_add(1).ok_or(DvpnError::MathOverflow)?;
+        emit!(NodeRated {
+            node: node.key(),
+            session: session.key(),
+            rating,
+            rating_sum: node.rating_sum,
+            rating_count: node.rating_count,
         });
         Ok(())
     }
-}
 
-#[derive(Accounts)]
-#[instruction(bump: u8)]
-pub struct InitializeState<'info> {
-    #[account(mut)]
-    pub authority: Signer<'info>,
-    #[account(
-        init,
-        payer = authority,
-        space = 8 + State::SIZE,
-        seeds = [b"state", authority.key().as_ref()],
+    /// Close a settled session, sweeping any escrow dust and returning rent to the user.
+    /// Not subject to pause.
+    pub fn close_session(ctx: Context<CloseSession>) -> Result<()> {
+        let session = &ctx.accounts.session;
+        require!(
+            matches!(session.status, SessionStatus::Settled | SessionStatus::Cancelled),
+            DvpnError::SessionOpen
+        );
+
+        let user_key = session.user;
+        let node_key = session.node;
+        let session_id = session.session_id.to_le_bytes();
+        let seeds: &[&[u8]] = &[
+            b"session",
+            user_key.as_ref(),
+            node_key.as_ref(),
+            &session_id,
+            &[session.bump]
+        ];
+        let accounts = &ctx.accounts;
+        let dust = accounts.escrow_token_account.amount;
+        if dust > 0 {
+            escrow_transfer(&accounts.token_program, &accounts.payment_mint, &accounts.escrow_token_account, &accounts.user_token_account, &accounts.session, seeds, dust)?;
+        }
+        // Token-2022 blocks closing an account that still holds withheld transfer fees
+        if transfer_fee_enabled(&accounts.payment_mint)? {
+            let cpi_accounts = HarvestWithheldTokensToMint {
+                token_program_id: accounts.token_program.to_account_info(),
+                mint: accounts.payment_mint.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new(accounts.token_program.to_account_info(), cpi_accounts);
+            harvest_withheld_tokens_to_mint(cpi_ctx, vec![accounts.escrow_token_account.to_account_info()])?;
+        }
+        let cpi_accounts = CloseAccount {
+            account: accounts.escrow_token_account.to_account_info(),
+            destination: accounts.user.to_account_info(),
+            authority: accounts.session.to_account_info(),
+        };
+        let signer = &[seeds];
+        let cpi_ctx = CpiContext::new_with_signer(accounts.token_program.to_account_info(), cpi_accounts, signer);
+        token_interface::close_account(cpi_ctx)?;
+
+        emit!(SessionClosed {
+            session: accounts.session.key(),
+            user: user_key,
+        });
+        Ok(())
+    }
+
+    /// Update the node's price schedule (operator only, rate limited).
+    /// Open sessions keep the schedule they were started with.
+    pub fn update_node_pricing(ctx: Context<NodeOperator>, pricing: PriceSchedule) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let node = &mut ctx.accounts.node;
+        require!(
+            node.pricing_updated_at == 0
+                || now >= node.pricing_updated_at.saturating_add(PRICING_COOLDOWN_SECS),
+            DvpnError::PricingCooldown
+        );
+        node.pricing = pricing;
+        node.pricing_updated_at = now;
+        emit!(NodePricingUpdated {
+            node: node.key(),
+            price_per_mib: pricing.price_per_mib,
+            price_per_minute: pricing.price_per_minute,
+            min_charge: pricing.min_charge,
+        });
+        Ok(())
+    }
+
+    /// Price the node's schedule in another accepted payment mint, or clear that
+    /// mint with `rate == 0` (operator only). Open sessions keep the rate they started with.
+    pub fn set_payment_rate(ctx: Context<SetPaymentRate>, mint: Pubkey, rate: u64) -> Result<()> {
+        let state = &ctx.accounts.state;
+        require!(mint != state.mint, DvpnError::InvalidPaymentRate);
+        require!(rate == 0 || state.accepts_payment_mint(&mint), DvpnError::PaymentMintNotAccepted);
+        let node = &mut ctx.accounts.node;
+        node.set_payment_rate(mint, rate)?;
+        emit!(PaymentRateUpdated {
+            node: node.key(),
+            mint,
+            rate,
+        });
+        Ok(())
+    }
+
+    /// Publish the node's region, endpoint and capabilities (operator only)
+    pub fn update_node_metadata(ctx: Context<NodeOperator>, metadata: NodeMetadata) -> Result<()> {
+        metadata.validate()?;
+        let node = &mut ctx.accounts.node;
+        node.metadata = metadata.clone();
+        emit!(NodeUpdated {
+            node: node.key(),
+            metadata,
+        });
+        Ok(())
+    }
+
+    /// Liveness signal from the operator or a registered attestor, optionally carrying the
+    /// node's reported uptime. Revives a node that `mark_inactive` took offline. Allowed
+    /// while paused so nodes don't all go stale during an emergency stop.
+    pub fn heartbeat(ctx: Context<Heartbeat>, uptime_secs: Option<u64>) -> Result<()> {
+        let signer = ctx.accounts.signer.key();
+        require!(
+            signer == ctx.accounts.node.operator || ctx.accounts.attestor_registry.is_attestor(&signer),
+            DvpnError::Unauthorized
+        );
+        let min_stake = ctx.accounts.state.min_stake_lamports;
+        let token_stake_rate = ctx.accounts.state.token_stake_rate;
+        let node = &mut ctx.accounts.node;
+        node.last_heartbeat_ts = Clock::get()?.unix_timestamp;
+        if let Some(uptime) = uptime_secs {
+            node.uptime_secs = uptime;
+        }
+        // Only undo a staleness deactivation, never an operator or slashing one
+        if node.marked_stale && node.unbonding_started_at == 0 && node.total_stake(token_stake_rate) >= min_stake {
+            node.active = true;
+            node.marked_stale = false;
+            emit!(NodeReactivated { node: node.key() });
+        }
+        Ok(())
+    }
+
+    /// Permissionless crank: deactivate a node whose last heartbeat is older than
+    /// `state.heartbeat_staleness_secs`
+    pub fn mark_inactive(ctx: Context<MarkInactive>) -> Result<()> {
+        require!(!ctx.accounts.state.paused, DvpnError::Paused);
+        let now = Clock::get()?.unix_timestamp;
+        let node = &mut ctx.accounts.node;
+        require!(node.active, DvpnError::NodeInactive);
+        require!(node.is_stale(now, ctx.accounts.state.heartbeat_staleness_secs), DvpnError::NodeNotStale);
+        node.active = false;
+        node.marked_stale = true;
+        emit!(NodeMarkedInactive {
+            node: node.key(),
+            last_heartbeat_ts: node.last_heartbeat_ts,
+        });
+        Ok(())
+    }
+
+    /// Stop accepting new sessions (operator only); undone by `reactivate_node`
+    pub fn deactivate_node(ctx: Context<NodeOperator>) -> Result<()> {
+        let node = &mut ctx.accounts.node;
+        node.active = false;
+        emit!(NodeDeactivated { node: node.key() });
+        Ok(())
+    }
+
+    /// Deactivate the node and start the unbonding timer for its stake
+    pub fn request_unstake(ctx: Context<NodeOperator>) -> Result<()> {
+        let node = &mut ctx.accounts.node;
+        require!(node.unbonding_started_at == 0, DvpnError::AlreadyUnbonding);
+        let now = Clock::get()?.unix_timestamp;
+        node.active = false;
+        node.unbonding_started_at = now;
+        emit!(UnstakeRequested {
+            node: node.key(),
+            stake_lamports: node.stake_lamports,
+            withdrawable_at: now.saturating_add(UNBONDING_PERIOD_SECS),
+        });
+        Ok(())
+    }
+
+    /// Bring a deactivated node back into service (operator only). The node must not be
+    /// unbonding, must meet `min_stake_lamports` and must be sending heartbeats.
+    pub fn reactivate_node(ctx: Context<ReactivateNode>) -> Result<()> {
+        let state = &ctx.accounts.state;
+        let now = Clock::get()?.unix_timestamp;
+        let node = &mut ctx.accounts.node;
+        require!(node.unbonding_started_at == 0, DvpnError::AlreadyUnbonding);
+        require!(node.total_stake(state.token_stake_rate) >= state.min_stake_lamports, DvpnError::InsufficientStake);
+        require!(!node.is_stale(now, state.heartbeat_staleness_secs), DvpnError::NodeStale);
+        node.active = true;
+        node.marked_stale = false;
+        emit!(NodeReactivated { node: node.key() });
+        Ok(())
+    }
+
+    /// Return the remaining stake and rent to the operator and close the node, along with
+    /// its token stake vault if it has one (any dust goes to `operator_token_account`).
+    /// Only allowed once unbonding has elapsed and no session is still open.
+    pub fn close_node(ctx: Context<CloseNode>) -> Result<()> {
+        require!(!ctx.accounts.state.paused, DvpnError::Paused);
+        require!(!ctx.accounts.node.paused, DvpnError::Paused);
+        let node = &ctx.accounts.node;
+        require!(node.unbonding_started_at != 0, DvpnError::NotUnbonding);
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            now >= node.unbonding_started_at.saturating_add(UNBONDING_PERIOD_SECS),
+            DvpnError::UnbondingNotElapsed
+        );
+        require!(node.open_sessions == 0, DvpnError::OpenSessions);
+        require!(node.unclaimed_reward == 0 && node.unclaimed_epochs == 0, DvpnError::UnclaimedRewards);
+        // The node account holds delegated lamports too; delegators must withdraw first
+        require!(node.delegation_shares == 0, DvpnError::DelegationsOutstanding);
+        require!(node.staked_tokens == 0 && node.slashed_tokens == 0, DvpnError::TokenStakeOutstanding);
+
+        let vault_info = ctx.accounts.vault.to_account_info();
+        if !vault_info.data_is_empty() {
+            let accounts = &ctx.accounts;
+            require_keys_eq!(*vault_info.owner, accounts.token_program.key(), DvpnError::InvalidVault);
+            let vault = TokenAccount::try_deserialize(&mut &vault_info.try_borrow_data()?[..])?;
+            let mint = accounts.vault_mint.as_ref().ok_or(DvpnError::InvalidVault)?;
+            require_keys_eq!(mint.key(), vault.mint, DvpnError::InvalidVault);
+
+            let operator = node.operator;
+            let seeds: &[&[u8]] = &[b"node", operator.as_ref(), &[ctx.bumps.node]];
+            if vault.amount > 0 {
+                let destination = accounts.operator_token_account.as_ref().ok_or(DvpnError::InvalidVault)?;
+                require_keys_eq!(destination.mint, vault.mint, DvpnError::InvalidVault);
+                transfer_tokens(
+                    &accounts.token_program,
+                    mint,
+                    vault_info.clone(),
+                    destination.to_account_info(),
+                    node.to_account_info(),
+                    &[seeds],
+                    vault.amount,
+                )?;
+            }
+            // Token-2022 blocks closing an account that still holds withheld transfer fees
+            if transfer_fee_enabled(mint)? {
+                let cpi_accounts = HarvestWithheldTokensToMint {
+                    token_program_id: accounts.token_program.to_account_info(),
+                    mint: mint.to_account_info(),
+                };
+                let cpi_ctx = CpiContext::new(accounts.token_program.to_account_info(), cpi_accounts);
+                harvest_withheld_tokens_to_mint(cpi_ctx, vec![vault_info.clone()])?;
+            }
+            let cpi_accounts = CloseAccount {
+                account: vault_info,
+                destination: accounts.operator.to_account_info(),
+                authority: node.to_account_info(),
+            };
+            let signer = &[seeds];
+            let cpi_ctx = CpiContext::new_with_signer(accounts.token_program.to_account_info(), cpi_accounts, signer);
+            token_interface::close_account(cpi_ctx)?;
+        }
+
+        emit!(NodeClosed {
+            node: node.key(),
+            operator: node.operator,
+            returned_lamports: node.to_account_info().lamports(),
+        });
+        Ok(())
+    }
+
+    /// Set the cut of delegators' rewards kept by the operator
+    pub fn set_commission(ctx: Context<NodeOperator>, commission_bps: u16) -> Result<()> {
+        require!(commission_bps <= MAX_BPS, DvpnError::InvalidCommission);
+        let node = &mut ctx.accounts.node;
+        node.commission_bps = commission_bps;
+        emit!(CommissionUpdated {
+            node: node.key(),
+            commission_bps,
+        });
+        Ok(())
+    }
+
+    /// Delegate SOL to a node. The lamports are held by the node account, count toward
+    /// its stake and are slashed pro rata with the operator's own stake.
+    pub fn delegate_stake(ctx: Context<DelegateStake>, lamports: u64) -> Result<()> {
+        require!(!ctx.accounts.state.paused, DvpnError::Paused);
+        require!(lamports > 0, DvpnError::InvalidDelegation);
+        require!(ctx.accounts.node.unbonding_started_at == 0, DvpnError::AlreadyUnbonding);
+
+        let node_key = ctx.accounts.node.key();
+        let delegator_key = ctx.accounts.delegator.key();
+        invoke(
+            &system_instruction::transfer(&delegator_key, &node_key, lamports),
+            &[
+                ctx.accounts.delegator.to_account_info(),
+                ctx.accounts.node.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+            ],
+        )?;
+
+        let node = &mut ctx.accounts.node;
+        let shares = node.shares_for(lamports)?;
+        let delegation = &mut ctx.accounts.delegation;
+        if delegation.delegator == Pubkey::default() {
+            delegation.node = node_key;
+            delegation.delegator = delegator_key;
+            delegation.bump = ctx.bumps.delegation;
+        }
+        delegation.accrue(node)?;
+        delegation.shares = delegation.shares.checked_add(shares).ok_or(DvpnError::MathOverflow)?;
+        delegation.sync_reward_debt(node);
+        node.delegated_lamports = node.delegated_lamports.checked_add(lamports).ok_or(DvpnError::MathOverflow)?;
+        node.delegation_shares = node.delegation_shares.checked_add(shares).ok_or(DvpnError::MathOverflow)?;
+
+        emit!(StakeDelegated {
+            node: node_key,
+            delegator: delegator_key,
+            lamports,
+            shares,
+        });
+        Ok(())
+    }
+
+    /// Start unbonding `shares`. They stay slashable and keep earning until withdrawn.
+    pub fn undelegate_stake(ctx: Context<UndelegateStake>, shares: u64) -> Result<()> {
+        let delegation = &mut ctx.accounts.delegation;
+        require!(delegation.unbonding_shares == 0, DvpnError::AlreadyUnbonding);
+        require!(shares > 0 && shares <= delegation.shares, DvpnError::InvalidDelegation);
+        let now = Clock::get()?.unix_timestamp;
+        delegation.unbonding_shares = shares;
+        delegation.unbonding_started_at = now;
+        emit!(UndelegationRequested {
+            node: delegation.node,
+            delegator: delegation.delegator,
+            shares,
+            withdrawable_at: now.saturating_add(UNBONDING_PERIOD_SECS),
+        });
+        Ok(())
+    }
+
+    /// Return the current value of the unbonded shares to the delegator
+    pub fn withdraw_delegation(ctx: Context<WithdrawDelegation>) -> Result<()> {
+        require!(!ctx.accounts.state.paused, DvpnError::Paused);
+        require!(!ctx.accounts.node.paused, DvpnError::Paused);
+        let min_stake = ctx.accounts.state.min_stake_lamports;
+        let token_stake_rate = ctx.accounts.state.token_stake_rate;
+        let node = &mut ctx.accounts.node;
+        let delegation = &mut ctx.accounts.delegation;
+        require!(delegation.unbonding_shares != 0, DvpnError::NotUnbonding);
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            now >= delegation.unbonding_started_at.saturating_add(UNBONDING_PERIOD_SECS),
+            DvpnError::UnbondingNotElapsed
+        );
+
+        let shares = delegation.unbonding_shares;
+        let lamports = node.shares_value(shares);
+        delegation.accrue(node)?;
+        delegation.shares = delegation.shares.checked_sub(shares).ok_or(DvpnError::MathOverflow)?;
+        delegation.unbonding_shares = 0;
+        delegation.unbonding_started_at = 0;
+        node.delegated_lamports = node.delegated_lamports.checked_sub(lamports).ok_or(DvpnError::MathOverflow)?;
+        node.delegation_shares = node.delegation_shares.checked_sub(shares).ok_or(DvpnError::MathOverflow)?;
+        delegation.sync_reward_debt(node);
+        if node.total_stake(token_stake_rate) < min_stake {
+            node.active = false;
+        }
+
+        let node_info = node.to_account_info();
+        let delegator_info = ctx.accounts.delegator.to_account_info();
+        **node_info.try_borrow_mut_lamports()? = node_info
+            .lamports()
+            .checked_sub(lamports)
+            .ok_or(DvpnError::MathOverflow)?;
+        **delegator_info.try_borrow_mut_lamports()? = delegator_info
+            .lamports()
+            .checked_add(lamports)
+            .ok_or(DvpnError::MathOverflow)?;
+
+        emit!(DelegationWithdrawn {
+            node: node.key(),
+            delegator: delegation.delegator,
+            shares,
+            lamports,
+        });
+        Ok(())
+    }
+
+    /// Mint the delegator's accrued share of the node's claimed rewards
+    pub fn claim_delegator_rewards(ctx: Context<ClaimDelegatorRewards>) -> Result<()> {
+        require!(!ctx.accounts.state.paused, DvpnError::Paused);
+        let delegation = &mut ctx.accounts.delegation;
+        delegation.accrue(&ctx.accounts.node)?;
+        let amount = delegation.pending_reward;
+        require!(amount > 0, DvpnError::NothingToClaim);
+        delegation.pending_reward = 0;
+
+        let cpi_accounts = MintTo {
+            mint: ctx.accounts.dvpn_mint.to_account_info(),
+            to: ctx.accounts.delegator_token_account.to_account_info(),
+            authority: ctx.accounts.state_signer.to_account_info(),
+        };
+        let seeds: &[&[u8]] = &[b"state", ctx.accounts.state.authority.as_ref(), &[ctx.accounts.state.bump]];
+        let signer = &[seeds];
+        let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), cpi_accounts, signer);
+        token_interface::mint_to(cpi_ctx, amount)?;
+        emit!(DelegatorRewardsClaimed {
+            node: ctx.accounts.node.key(),
+            delegator: ctx.accounts.delegator.key(),
+            amount,
+        });
+        Ok(())
+    }
+
+    /// Lock protocol tokens in the node's vault as stake. They count toward the
+    /// minimum stake at `state.token_stake_rate` and are slashed alongside SOL stake.
+    /// An inactive node that now meets the minimum and is sending heartbeats is activated.
+    pub fn stake_tokens(ctx: Context<StakeTokens>, amount: u64) -> Result<()> {
+        require!(!ctx.accounts.state.paused, DvpnError::Paused);
+        require!(amount > 0, DvpnError::InvalidDeposit);
+        require!(ctx.accounts.node.unbonding_started_at == 0, DvpnError::AlreadyUnbonding);
+
+        let amount = transfer_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.dvpn_mint,
+            ctx.accounts.operator_token_account.to_account_info(),
+            ctx.accounts.vault.to_account_info(),
+            ctx.accounts.operator.to_account_info(),
+            &[],
+            amount,
+        )?;
+
+        let state = &mut ctx.accounts.state;
+        state.total_vault_tokens = state.total_vault_tokens.checked_add(amount).ok_or(DvpnError::MathOverflow)?;
+        let node = &mut ctx.accounts.node;
+        node.staked_tokens = node.staked_tokens.checked_add(amount).ok_or(DvpnError::MathOverflow)?;
+        emit!(TokensStaked {
+            node: node.key(),
+            amount,
+            staked_tokens: node.staked_tokens,
+        });
+
+        let now = Clock::get()?.unix_timestamp;
+        if !node.active
+            && node.total_stake(state.token_stake_rate) >= state.min_stake_lamports
+            && !node.is_stale(now, state.heartbeat_staleness_secs)
+        {
+            node.active = true;
+            node.marked_stale = false;
+            emit!(NodeReactivated { node: node.key() });
+        }
+        Ok(())
+    }
+
+    /// Start unbonding `amount` of token stake. It stays slashable until withdrawn.
+    pub fn request_token_unstake(ctx: Context<NodeOperator>, amount: u64) -> Result<()> {
+        let node = &mut ctx.accounts.node;
+        require!(node.token_unbonding_amount == 0, DvpnError::AlreadyUnbonding);
+        require!(amount > 0 && amount <= node.staked_tokens, DvpnError::InsufficientStake);
+        let now = Clock::get()?.unix_timestamp;
+        node.token_unbonding_amount = amount;
+        node.token_unbonding_started_at = now;
+        emit!(TokenUnstakeRequested {
+            node: node.key(),
+            amount,
+            withdrawable_at: now.saturating_add(UNBONDING_PERIOD_SECS),
+        });
+        Ok(())
+    }
+
+    /// Return unbonded token stake to the operator
+    pub fn withdraw_tokens(ctx: Context<WithdrawTokens>) -> Result<()> {
+        require!(!ctx.accounts.state.paused, DvpnError::Paused);
+        require!(!ctx.accounts.node.paused, DvpnError::Paused);
+        let node = &ctx.accounts.node;
+        require!(node.token_unbonding_started_at != 0, DvpnError::NotUnbonding);
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            now >= node.token_unbonding_started_at.saturating_add(UNBONDING_PERIOD_SECS),
+            DvpnError::UnbondingNotElapsed
+        );
+
+        // Slashes during unbonding may have shrunk this, possibly to zero
+        let amount = node.token_unbonding_amount;
+        if amount > 0 {
+            let operator = node.operator;
+            let seeds: &[&[u8]] = &[b"node", operator.as_ref(), &[ctx.bumps.node]];
+            transfer_tokens(
+                &ctx.accounts.token_program,
+                &ctx.accounts.dvpn_mint,
+                ctx.accounts.vault.to_account_info(),
+                ctx.accounts.operator_token_account.to_account_info(),
+                ctx.accounts.node.to_account_info(),
+                &[seeds],
+                amount,
+            )?;
+        }
+
+        let state = &mut ctx.accounts.state;
+        state.total_vault_tokens = state.total_vault_tokens.checked_sub(amount).ok_or(DvpnError::MathOverflow)?;
+        let node = &mut ctx.accounts.node;
+        node.staked_tokens = node.staked_tokens.checked_sub(amount).ok_or(DvpnError::MathOverflow)?;
+        node.token_unbonding_amount = 0;
+        node.token_unbonding_started_at = 0;
+        if node.total_stake(state.token_stake_rate) < state.min_stake_lamports {
+            node.active = false;
+        }
+        emit!(TokensWithdrawn {
+            node: node.key(),
+            amount,
+            staked_tokens: node.staked_tokens,
+        });
+        Ok(())
+    }
+
+    /// Permissionless crank: move slashed token stake from the node vault to the fee treasury
+    pub fn sweep_slashed_tokens(ctx: Context<SweepSlashedTokens>) -> Result<()> {
+        let amount = ctx.accounts.node.slashed_tokens;
+        require!(amount > 0, DvpnError::NothingToClaim);
+
+        let operator = ctx.accounts.node.operator;
+        let seeds: &[&[u8]] = &[b"node", operator.as_ref(), &[ctx.bumps.node]];
+        transfer_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.dvpn_mint,
+            ctx.accounts.vault.to_account_info(),
+            ctx.accounts.treasury_token_account.to_account_info(),
+            ctx.accounts.node.to_account_info(),
+            &[seeds],
+            amount,
+        )?;
+
+        let state = &mut ctx.accounts.state;
+        state.total_vault_tokens = state.total_vault_tokens.checked_sub(amount).ok_or(DvpnError::MathOverflow)?;
+        ctx.accounts.node.slashed_tokens = 0;
+        emit!(SlashedTokensSwept {
+            node: ctx.accounts.node.key(),
+            amount,
+        });
+        Ok(())
+    }
+
+    /// Create the treasury PDA that receives slashed stake (authority only). Only needed for
+    /// states created before `initialize_state` made the treasury; `migrate_state` also does it.
+    pub fn initialize_treasury(ctx: Context<InitializeTreasury>) -> Result<()> {
+        require_keys_eq!(ctx.accounts.state.fee_manager, ctx.accounts.authority.key(), DvpnError::Unauthorized);
+        let treasury = &mut ctx.accounts.treasury;
+        treasury.state = ctx.accounts.state.key();
+        treasury.total_slashed_lamports = 0;
+        treasury.bump = ctx.bumps.treasury;
+        treasury.total_fees_collected = 0;
+        treasury.total_fees_withdrawn = 0;
+        Ok(())
+    }
+
+    /// Configure the protocol fee and the token account it is paid into (authority only).
+    /// The fee account must hold `state.mint` and be owned by the treasury PDA.
+    pub fn set_fee_treasury(ctx: Context<SetFeeConfig>) -> Result<()> {
+        require_keys_eq!(ctx.accounts.state.fee_manager, ctx.accounts.authority.key(), DvpnError::Unauthorized);
+        let state = &mut ctx.accounts.state;
+        state.fee_treasury = ctx.accounts.treasury_token_account.key();
+        emit!(FeeConfigUpdated {
+            protocol_fee_bps: state.protocol_fee_bps,
+            fee_treasury: state.fee_treasury,
+        });
+        Ok(())
+    }
+
+    /// Withdraw collected protocol fees in any payment mint from the treasury (authority only)
+    pub fn withdraw_treasury(ctx: Context<WithdrawTreasury>, amount: u64) -> Result<()> {
+        require!(!ctx.accounts.state.paused, DvpnError::Paused);
+        require_keys_eq!(ctx.accounts.state.fee_manager, ctx.accounts.authority.key(), DvpnError::Unauthorized);
+        let state_key = ctx.accounts.state.key();
+        let seeds: &[&[u8]] = &[b"treasury", state_key.as_ref(), &[ctx.accounts.treasury.bump]];
+        transfer_tokens(
+            &ctx.accounts.token_program,
+            &ctx.accounts.mint,
+            ctx.accounts.treasury_token_account.to_account_info(),
+            ctx.accounts.destination.to_account_info(),
+            ctx.accounts.treasury.to_account_info(),
+            &[seeds],
+            amount,
+        )?;
+
+        if ctx.accounts.mint.key() == ctx.accounts.state.mint {
+            let treasury = &mut ctx.accounts.treasury;
+            treasury.total_fees_withdrawn = treasury.total_fees_withdrawn
+                .checked_add(amount)
+                .ok_or(DvpnError::MathOverflow)?;
+        }
+        emit!(TreasuryWithdrawn {
+            destination: ctx.accounts.destination.key(),
+            amount,
+        });
+        Ok(())
+    }
+
+    /// Set the stake below which a slashed node is deactivated (authority only)
+    pub fn set_min_stake(ctx: Context<SetMinStake>, min_stake_lamports: u64) -> Result<()> {
+        require_keys_eq!(ctx.accounts.state.admin, ctx.accounts.authority.key(), DvpnError::Unauthorized);
+        ctx.accounts.state.min_stake_lamports = min_stake_lamports;
+        Ok(())
+    }
+
+    /// Configure the settlement dispute window and penalties (admin only)
+    pub fn set_dispute_config(
+        ctx: Context<SetMinStake>,
+        dispute_window_secs: i64,
+        dispute_bond: u64,
+        dispute_slash_lamports: u64,
+    ) -> Result<()> {
+        require_keys_eq!(ctx.accounts.state.admin, ctx.accounts.authority.key(), DvpnError::Unauthorized);
+        require!(
+            (0..=MAX_DISPUTE_WINDOW_SECS).contains(&dispute_window_secs),
+            DvpnError::InvalidDuration
+        );
+        let state = &mut ctx.accounts.state;
+        state.dispute_window_secs = dispute_window_secs;
+        state.dispute_bond = dispute_bond;
+        state.dispute_slash_lamports = dispute_slash_lamports;
+        emit!(DisputeConfigUpdated {
+            dispute_window_secs,
+            dispute_bond,
+            dispute_slash_lamports,
+        });
+        Ok(())
+    }
+
+    /// Set how long a node may go without a heartbeat before it counts as stale (admin only)
+    pub fn set_heartbeat_window(ctx: Context<SetMinStake>, heartbeat_staleness_secs: i64) -> Result<()> {
+        require_keys_eq!(ctx.accounts.state.admin, ctx.accounts.authority.key(), DvpnError::Unauthorized);
+        require!(heartbeat_staleness_secs > 0, DvpnError::InvalidDuration);
+        ctx.accounts.state.heartbeat_staleness_secs = heartbeat_staleness_secs;
+        Ok(())
+    }
+
+    /// Set the stake credit given to token stake, in lamports per `TOKEN_STAKE_RATE_SCALE`
+    /// base units; 0 gives token stake no weight (admin only)
+    pub fn set_token_stake_rate(ctx: Context<SetMinStake>, token_stake_rate: u64) -> Result<()> {
+        require_keys_eq!(ctx.accounts.state.admin, ctx.accounts.authority.key(), DvpnError::Unauthorized);
+        ctx.accounts.state.token_stake_rate = token_stake_rate;
+        Ok(())
+    }
+
+    /// Slash node for misbehavior, moving the slashed lamports to the treasury
+    pub fn slash_node(ctx: Context<SlashNode>, amount: u64, reason: SlashReason, evidence_hash: [u8; 32]) -> Result<()> {
+        require!(!ctx.accounts.state.paused, DvpnError::Paused);
+        require_signer_quorum(&ctx.accounts.attestor_registry, &ctx.accounts.authority.key(), ctx.remaining_accounts)?;
+
+        let accounts = &mut *ctx.accounts;
+        slash_stake(
+            &mut accounts.node,
+            &mut accounts.treasury,
+            &accounts.state,
+            amount,
+            reason,
+            evidence_hash,
+        )?;
+        Ok(())
+    }
+}
+
+/// PDA of a user's session counter
+pub fn session_counter_pda(user: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"session_counter", user.as_ref()], &ID)
+}
+
+/// PDA of the `session_id`-th session between `user` and `node`
+pub fn session_pda(user: &Pubkey, node: &Pubkey, session_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"session", user.as_ref(), node.as_ref(), &session_id.to_le_bytes()],
+        &ID,
+    )
+}
+
+/// Bounds for `rate_node`
+pub const MIN_RATING: u8 = 1;
+pub const MAX_RATING: u8 = 5;
+
+/// Dispute window set by `initialize_state`, and the most `set_dispute_config` allows
+pub const DEFAULT_DISPUTE_WINDOW_SECS: i64 = 24 * 60 * 60;
+pub const MAX_DISPUTE_WINDOW_SECS: i64 = 7 * 24 * 60 * 60;
+
+/// Time attestors or a prover have to resolve a dispute before `expire_dispute` may settle it
+pub const DISPUTE_RESOLUTION_SECS: i64 = 7 * 24 * 60 * 60;
+
+/// Dispute penalties set by `initialize_state`; the bond is in whole tokens of `state.mint`
+pub const DEFAULT_DISPUTE_BOND_TOKENS: u64 = 10;
+pub const DEFAULT_DISPUTE_SLASH_LAMPORTS: u64 = 100_000_000;
+
+/// Default for `State::heartbeat_staleness_secs`
+pub const DEFAULT_HEARTBEAT_STALENESS_SECS: i64 = 15 * 60;
+
+/// Upper bound on a session's `max_duration`
+pub const MAX_SESSION_DURATION_SECS: i64 = 30 * 24 * 60 * 60;
+
+/// Time a node must wait between `request_unstake` and `close_node`
+pub const UNBONDING_PERIOD_SECS: i64 = 7 * 24 * 60 * 60;
+
+/// Basis point denominator
+pub const MAX_BPS: u16 = 10_000;
+
+/// Timelock applied to governance proposals until changed by one
+pub const DEFAULT_TIMELOCK_DELAY_SECS: i64 = 2 * 24 * 60 * 60;
+
+/// Bounds for `GovernanceAction::SetTimelockDelay`
+pub const MIN_TIMELOCK_DELAY_SECS: i64 = 60 * 60;
+pub const MAX_TIMELOCK_DELAY_SECS: i64 = 30 * 24 * 60 * 60;
+
+/// How long after its `eta` a proposal stays executable
+pub const PROPOSAL_GRACE_PERIOD_SECS: i64 = 14 * 24 * 60 * 60;
+
+/// Supply cap set by `initialize_state`, in whole tokens
+pub const DEFAULT_MAX_SUPPLY_TOKENS: u64 = 1_000_000_000;
+
+/// Convert whole tokens to base units using the mint's decimals
+pub fn token_amount(tokens: u64, decimals: u8) -> Result<u64> {
+    10u64
+        .checked_pow(decimals as u32)
+        .and_then(|unit| tokens.checked_mul(unit))
+        .ok_or(error!(DvpnError::MathOverflow))
+}
+
+/// Scale raw bytes by a multiplier in basis points
+pub fn weighted_bytes(bytes: u64, multiplier_bps: u32) -> u64 {
+    ((bytes as u128) * (multiplier_bps as u128) / (MAX_BPS as u128)).min(u64::MAX as u128) as u64
+}
+
+/// Node's share of a finalized epoch budget: `budget * node_bytes / total_bytes`
+pub fn epoch_share(budget: u64, node_bytes: u64, total_bytes: u64) -> u64 {
+    if total_bytes == 0 {
+        return 0;
+    }
+    ((budget as u128) * (node_bytes as u128) / (total_bytes as u128)) as u64
+}
+
+/// Validate an `(epoch, node_epoch)` pair from `claim_rewards`, mark it claimed and
+/// return the node's share
+fn claim_epoch_share(
+    state: &Pubkey,
+    node: &Pubkey,
+    epoch_info: &AccountInfo,
+    node_epoch_info: &AccountInfo,
+    now: i64,
+) -> Result<u64> {
+    require_keys_eq!(*epoch_info.owner, crate::ID, DvpnError::InvalidEpoch);
+    require_keys_eq!(*node_epoch_info.owner, crate::ID, DvpnError::InvalidEpoch);
+    let epoch = Epoch::try_deserialize(&mut &epoch_info.try_borrow_data()?[..])?;
+    let mut node_epoch = NodeEpoch::try_deserialize(&mut &node_epoch_info.try_borrow_data()?[..])?;
+    require_keys_eq!(epoch.state, *state, DvpnError::InvalidEpoch);
+    require_keys_eq!(node_epoch.node, *node, DvpnError::InvalidEpoch);
+    require!(node_epoch.epoch == epoch.id, DvpnError::InvalidEpoch);
+    require!(now >= epoch.end_ts, DvpnError::EpochNotFinalized);
+    require!(!node_epoch.claimed, DvpnError::RewardAlreadyClaimed);
+
+    node_epoch.claimed = true;
+    node_epoch.try_serialize(&mut &mut node_epoch_info.try_borrow_mut_data()?[..])?;
+    Ok(epoch_share(epoch.emission_budget, node_epoch.weighted_bytes, epoch.total_weighted_bytes))
+}
+
+/// Share of a settlement payout owed to the protocol
+pub fn protocol_fee(payout: u64, fee_bps: u16) -> u64 {
+    ((payout as u128) * (fee_bps as u128) / (MAX_BPS as u128)) as u64
+}
+
+/// Minimum time between two `update_node_pricing` calls
+pub const PRICING_COOLDOWN_SECS: i64 = 24 * 60 * 60;
+
+const BYTES_PER_MIB: u128 = 1_048_576;
+
+/// Amount owed for a session under `pricing`, capped at the deposit:
+/// max(bytes / MiB * price_per_mib + started minutes * price_per_minute, min_charge)
+pub fn compute_payout(pricing: &PriceSchedule, bytes: u64, elapsed_secs: i64, deposit: u64) -> Result<u64> {
+    let bytes_charge = (bytes as u128)
+        .checked_mul(pricing.price_per_mib as u128)
+        .ok_or(DvpnError::MathOverflow)?
+        / BYTES_PER_MIB;
+    let minutes = (elapsed_secs.max(0) as u128).div_ceil(60);
+    let time_charge = minutes
+        .checked_mul(pricing.price_per_minute as u128)
+        .ok_or(DvpnError::MathOverflow)?;
+    let total = bytes_charge
+        .checked_add(time_charge)
+        .ok_or(DvpnError::MathOverflow)?
+        .max(pricing.min_charge as u128);
+    Ok(total.min(deposit as u128) as u64)
+}
+
+/// Domain separator for attestor-signed settlements
+pub const ATTESTATION_DOMAIN: &[u8] = b"dvpn:settle:v1";
+
+/// Canonical message an attestor signs to settle a session:
+/// domain || session || user || node || total_bytes (LE) || nonce (LE) || expires_at (LE)
+pub fn attestation_message(
+    session: &Pubkey,
+    user: &Pubkey,
+    node: &Pubkey,
+    total_bytes: u64,
+    nonce: u64,
+    expires_at: i64,
+) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(ATTESTATION_DOMAIN.len() + 32 * 3 + 8 * 3);
+    msg.extend_from_slice(ATTESTATION_DOMAIN);
+    msg.extend_from_slice(session.as_ref());
+    msg.extend_from_slice(user.as_ref());
+    msg.extend_from_slice(node.as_ref());
+    msg.extend_from_slice(&total_bytes.to_le_bytes());
+    msg.extend_from_slice(&nonce.to_le_bytes());
+    msg.extend_from_slice(&expires_at.to_le_bytes());
+    msg
+}
+
+pub const USAGE_DOMAIN: &[u8] = b"dvpn:usage:v1";
+
+/// Message a user signs to accept the byte intervals behind a bandwidth proof:
+/// domain || session || usage_commitment (the circuit's Poseidon hash of the intervals)
+pub fn usage_message(session: &Pubkey, usage_commitment: &[u8; 32]) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(USAGE_DOMAIN.len() + 32 + 32);
+    msg.extend_from_slice(USAGE_DOMAIN);
+    msg.extend_from_slice(session.as_ref());
+    msg.extend_from_slice(usage_commitment);
+    msg
+}
+
+pub const RECEIPT_DOMAIN: &[u8] = b"dvpn:receipt:v2";
+
+/// Message a user signs off-chain to acknowledge usage in payment-channel mode:
+/// domain || session || cumulative_bytes (LE) || expires_at (LE)
+pub fn receipt_message(session: &Pubkey, cumulative_bytes: u64, expires_at: i64) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(RECEIPT_DOMAIN.len() + 32 + 8 + 8);
+    msg.extend_from_slice(RECEIPT_DOMAIN);
+    msg.extend_from_slice(session.as_ref());
+    msg.extend_from_slice(&cumulative_bytes.to_le_bytes());
+    msg.extend_from_slice(&expires_at.to_le_bytes());
+    msg
+}
+
+/// Extract the signer and message from an Ed25519 program instruction carrying a
+/// single signature with all data inline in the instruction itself.
+/// The Ed25519 program has already verified the signature by the time we run.
+pub fn parse_ed25519_ix(ix: &Instruction) -> Result<(Pubkey, &[u8])> {
+    require_keys_eq!(ix.program_id, ed25519_program::ID, DvpnError::MissingEd25519Instruction);
+    require!(ix.accounts.is_empty(), DvpnError::InvalidAttestation);
+
+    let data = &ix.data;
+    // Header: num_signatures (u8) + padding (u8), then one 14-byte offsets struct
+    require!(data.len() >= 16 && data[0] == 1, DvpnError::InvalidAttestation);
+    let read_u16 = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+    let sig_ix_index = read_u16(4);
+    let pubkey_offset = read_u16(6) as usize;
+    let pubkey_ix_index = read_u16(8);
+    let msg_offset = read_u16(10) as usize;
+    let msg_size = read_u16(12) as usize;
+    let msg_ix_index = read_u16(14);
+
+    // Offsets must point into this same instruction, not some other one in the tx
+    require!(
+        sig_ix_index == u16::MAX && pubkey_ix_index == u16::MAX && msg_ix_index == u16::MAX,
+        DvpnError::InvalidAttestation
+    );
+
+    let pubkey_bytes = data.get(pubkey_offset..pubkey_offset + 32).ok_or(DvpnError::InvalidAttestation)?;
+    let signer = Pubkey::try_from(pubkey_bytes).map_err(|_| DvpnError::InvalidAttestation)?;
+    let msg_bytes = data.get(msg_offset..msg_offset + msg_size).ok_or(DvpnError::InvalidAttestation)?;
+    Ok((signer, msg_bytes))
+}
+
+/// Check that `ix` is an Ed25519 program instruction in which `signer` signs `message`
+pub fn verify_ed25519_ix(ix: &Instruction, signer: &Pubkey, message: &[u8]) -> Result<()> {
+    let (actual_signer, signed) = parse_ed25519_ix(ix)?;
+    require_keys_eq!(actual_signer, *signer, DvpnError::Unauthorized);
+    require!(signed == message, DvpnError::InvalidAttestation);
+    Ok(())
+}
+
+/// Distinct registered attestors that signed `message` in Ed25519 instructions
+/// preceding the current one. Ed25519 instructions over other messages, and ones that
+/// aren't single-signature with inline data, are ignored.
+pub fn ed25519_approvals(ix_sysvar: &AccountInfo, registry: &AttestorRegistry, message: &[u8]) -> Result<Vec<Pubkey>> {
+    let current = load_current_index_checked(ix_sysvar)? as usize;
+    let mut approved: Vec<Pubkey> = Vec::new();
+    for index in 0..current {
+        let ix = load_instruction_at_checked(index, ix_sysvar)?;
+        if ix.program_id != ed25519_program::ID {
+            continue;
+        }
+        let Ok((signer, signed)) = parse_ed25519_ix(&ix) else {
+            continue;
+        };
+        if signed == message && registry.is_attestor(&signer) && !approved.contains(&signer) {
+            approved.push(signer);
+        }
+    }
+    Ok(approved)
+}
+
+/// Require `threshold` distinct registered attestors among `primary` and any
+/// additional signer accounts passed as remaining accounts
+pub fn require_signer_quorum(registry: &AttestorRegistry, primary: &Pubkey, extra: &[AccountInfo]) -> Result<()> {
+    let mut approved: Vec<Pubkey> = Vec::new();
+    let co_signers = extra.iter().filter(|a| a.is_signer).map(|a| *a.key);
+    for key in std::iter::once(*primary).chain(co_signers) {
+        if registry.is_attestor(&key) && !approved.contains(&key) {
+            approved.push(key);
+        }
+    }
+    require!(!approved.is_empty(), DvpnError::Unauthorized);
+    require!(approved.len() >= registry.threshold as usize, DvpnError::InsufficientApprovals);
+    Ok(())
+}
+
+/// The mint's Token-2022 transfer-fee extension, if it has one
+fn transfer_fee_config(mint: &InterfaceAccount<Mint>) -> Result<Option<TransferFeeConfig>> {
+    let mint_info = mint.to_account_info();
+    if *mint_info.owner != anchor_spl::token_2022::ID {
+        return Ok(None);
+    }
+    let data = mint_info.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<anchor_spl::token_2022::spl_token_2022::state::Mint>::unpack(&data)?;
+    Ok(mint_state.get_extension::<TransferFeeConfig>().ok().copied())
+}
+
+pub fn transfer_fee_enabled(mint: &InterfaceAccount<Mint>) -> Result<bool> {
+    Ok(transfer_fee_config(mint)?.is_some())
+}
+
+/// Token-2022 transfer fee `mint` charges on `amount` in the current epoch; 0 for SPL Token
+/// mints and Token-2022 mints without the extension
+pub fn transfer_fee(mint: &InterfaceAccount<Mint>, amount: u64) -> Result<u64> {
+    match transfer_fee_config(mint)? {
+        Some(config) => config
+            .calculate_epoch_fee(Clock::get()?.epoch, amount)
+            .ok_or_else(|| error!(DvpnError::MathOverflow)),
+        None => Ok(0),
+    }
+}
+
+/// `transfer_checked` under either token program. Returns what `to` was credited,
+/// i.e. `amount` less any transfer fee withheld by the mint.
+fn transfer_tokens<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    mint: &InterfaceAccount<'info, Mint>,
+    from: AccountInfo<'info>,
+    to: AccountInfo<'info>,
+    authority: AccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
+    amount: u64,
+) -> Result<u64> {
+    let cpi_accounts = TransferChecked {
+        from,
+        mint: mint.to_account_info(),
+        to,
+        authority,
+    };
+    let cpi_ctx = CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer_seeds);
+    token_interface::transfer_checked(cpi_ctx, amount, mint.decimals)?;
+    Ok(amount - transfer_fee(mint, amount)?)
+}
+
+/// Transfer `amount` out of a session escrow, signed by the session PDA
+fn escrow_transfer<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    mint: &InterfaceAccount<'info, Mint>,
+    escrow: &InterfaceAccount<'info, TokenAccount>,
+    to: &InterfaceAccount<'info, TokenAccount>,
+    session: &Account<'info, Session>,
+    seeds: &[&[u8]],
+    amount: u64,
+) -> Result<u64> {
+    transfer_tokens(
+        token_program,
+        mint,
+        escrow.to_account_info(),
+        to.to_account_info(),
+        session.to_account_info(),
+        &[seeds],
+        amount,
+    )
+}
+
+/// How a session's escrow is split when it settles
+pub struct SessionPayout {
+    pub node_amount: u64,
+    pub protocol_fee: u64,
+    pub refund: u64,
+}
+
+impl SessionPayout {
+    /// Charge `bytes` plus session time up to `ended_at` against the deposit
+    pub fn compute(session: &Session, bytes: u64, fee_bps: u16) -> Result<Self> {
+        let elapsed = session.billable_secs(session.ended_at);
+        let payout = compute_payout(&session.pricing, bytes, elapsed, session.deposit_amount)?;
+        let protocol_fee = protocol_fee(payout, fee_bps);
+        Ok(Self {
+            node_amount: payout.saturating_sub(protocol_fee),
+            protocol_fee,
+            // Unspent deposit goes back to the user
+            refund: session.deposit_amount.saturating_sub(payout),
+        })
+    }
+}
+
+/// Pay a session's node share, protocol fee and refund out of escrow
+#[allow(clippy::too_many_arguments)]
+fn disburse_session<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    mint: &InterfaceAccount<'info, Mint>,
+    escrow: &InterfaceAccount<'info, TokenAccount>,
+    session: &Account<'info, Session>,
+    node_token_account: &InterfaceAccount<'info, TokenAccount>,
+    user_token_account: &InterfaceAccount<'info, TokenAccount>,
+    treasury_token_account: Option<&InterfaceAccount<'info, TokenAccount>>,
+    payout: &SessionPayout,
+) -> Result<()> {
+    let session_id = session.session_id.to_le_bytes();
+    let seeds: &[&[u8]] = &[
+        b"session",
+        session.user.as_ref(),
+        session.node.as_ref(),
+        &session_id,
+        &[session.bump],
+    ];
+    if payout.node_amount > 0 {
+        escrow_transfer(token_program, mint, escrow, node_token_account, session, seeds, payout.node_amount)?;
+    }
+    if payout.protocol_fee > 0 {
+        let treasury_token_account = treasury_token_account.ok_or(DvpnError::InvalidTreasury)?;
+        escrow_transfer(token_program, mint, escrow, treasury_token_account, session, seeds, payout.protocol_fee)?;
+    }
+    if payout.refund > 0 {
+        escrow_transfer(token_program, mint, escrow, user_token_account, session, seeds, payout.refund)?;
+    }
+    Ok(())
+}
+
+/// End an active session on its last submitted usage and start its dispute window
+fn open_dispute_window(session: &mut Account<Session>, state: &State, now: i64) -> Result<()> {
+    session.status = SessionStatus::Pending;
+    session.ended_at = now;
+    session.dispute_deadline = now
+        .checked_add(state.dispute_window_secs)
+        .ok_or(DvpnError::MathOverflow)?;
+    emit!(SettlementRequested {
+        session: session.key(),
+        bytes: session.bytes_used,
+        dispute_deadline: session.dispute_deadline,
+    });
+    Ok(())
+}
+
+/// Bookkeeping once a session's escrow has been split
+fn record_settlement(
+    session: &mut Session,
+    node: &mut Node,
+    treasury: &mut Treasury,
+    state: &State,
+    payout: &SessionPayout,
+) -> Result<()> {
+    session.status = SessionStatus::Settled;
+    node.open_sessions = node.open_sessions.saturating_sub(1);
+    // Fee totals are kept in `state.mint`; other payment mints are tracked by their token accounts
+    if session.payment_mint == state.mint {
+        treasury.total_fees_collected = treasury
+            .total_fees_collected
+            .checked_add(payout.protocol_fee)
+            .ok_or(DvpnError::MathOverflow)?;
+    }
+    Ok(())
+}
+
+/// Settle on usage backed by an attestor quorum, a proof or a user receipt. For a disputed session the
+/// party whose count is further from `total_bytes` loses: the user's bond goes to the
+/// node, or the node is slashed `state.dispute_slash_lamports` and the bond is returned.
+fn apply_verified_usage(
+    session: &mut Account<Session>,
+    node: &mut Account<Node>,
+    treasury: &mut Account<Treasury>,
+    state: &State,
+    total_bytes: u64,
+    now: i64,
+) -> Result<SessionPayout> {
+    let disputed = session.status == SessionStatus::Disputed;
+    let node_bytes = session.bytes_used;
+    if session.status == SessionStatus::Active {
+        session.ended_at = now;
+    }
+    session.bytes_used = total_bytes;
+    let mut payout = SessionPayout::compute(session, total_bytes, state.effective_fee_bps())?;
+
+    if disputed {
+        let user_won = total_bytes.abs_diff(session.disputed_bytes) < total_bytes.abs_diff(node_bytes);
+        let bond = session.dispute_bond;
+        let mut slashed = 0;
+        if user_won {
+            payout.refund = payout.refund.checked_add(bond).ok_or(DvpnError::MathOverflow)?;
+            slashed = slash_stake(
+                node,
+                treasury,
+                state,
+                state.dispute_slash_lamports,
+                SlashReason::InvalidUsage,
+                session.key().to_bytes(),
+            )?;
+        } else {
+            payout.node_amount = payout.node_amount.checked_add(bond).ok_or(DvpnError::MathOverflow)?;
+        }
+        emit!(DisputeResolved {
+            session: session.key(),
+            resolved_bytes: total_bytes,
+            node_bytes,
+            user_bytes: session.disputed_bytes,
+            user_won,
+            bond,
+            slashed,
+        });
+    }
+
+    record_settlement(session, node, treasury, state, &payout)?;
+    Ok(payout)
+}
+
+/// Move up to `amount` of a node's staked lamports to the treasury and record the slash.
+/// Returns the amount actually slashed.
+fn slash_stake(
+    node: &mut Account<Node>,
+    treasury: &mut Account<Treasury>,
+    state: &State,
+    amount: u64,
+    reason: SlashReason,
+    evidence_hash: [u8; 32],
+) -> Result<u64> {
+    // Operator, delegators and token stake all lose the same fraction. `amount` is in
+    // lamports of stake credit; the token part is converted back at the stake rate.
+    let total = node.total_stake(state.token_stake_rate);
+    let token_credit = node.token_stake_credit(state.token_stake_rate);
+    let slashed = amount.min(total);
+    let (token_credit_slashed, tokens_slashed) = if token_credit == 0 {
+        (0, 0)
+    } else {
+        let credit = ((slashed as u128) * (token_credit as u128) / (total as u128)) as u64;
+        (credit, ((node.staked_tokens as u128) * (credit as u128) / (token_credit as u128)) as u64)
+    };
+    let lamport_stake = node.stake_lamports.saturating_add(node.delegated_lamports);
+    let lamports_slashed = (slashed - token_credit_slashed).min(lamport_stake);
+    let delegator_slashed = if node.delegated_lamports == 0 {
+        0
+    } else {
+        ((lamports_slashed as u128) * (node.delegated_lamports as u128) / (lamport_stake as u128)) as u64
+    };
+    let slashed = lamports_slashed + token_credit_slashed;
+    if lamports_slashed > 0 {
+        let node_info = node.to_account_info();
+        let treasury_info = treasury.to_account_info();
+        **node_info.try_borrow_mut_lamports()? = node_info
+            .lamports()
+            .checked_sub(lamports_slashed)
+            .ok_or(DvpnError::MathOverflow)?;
+        **treasury_info.try_borrow_mut_lamports()? = treasury_info
+            .lamports()
+            .checked_add(lamports_slashed)
+            .ok_or(DvpnError::MathOverflow)?;
+    }
+    treasury.total_slashed_lamports = treasury.total_slashed_lamports
+        .checked_add(lamports_slashed)
+        .ok_or(DvpnError::MathOverflow)?;
+
+    let now = Clock::get()?.unix_timestamp;
+    node.stake_lamports = node
+        .stake_lamports
+        .checked_sub(lamports_slashed - delegator_slashed)
+        .ok_or(DvpnError::MathOverflow)?;
+    node.delegated_lamports = node.delegated_lamports.checked_sub(delegator_slashed).ok_or(DvpnError::MathOverflow)?;
+    // Slashed tokens stay in the vault until `sweep_slashed_tokens` moves them to the treasury
+    node.staked_tokens = node.staked_tokens.checked_sub(tokens_slashed).ok_or(DvpnError::MathOverflow)?;
+    node.slashed_tokens = node.slashed_tokens.checked_add(tokens_slashed).ok_or(DvpnError::MathOverflow)?;
+    node.token_unbonding_amount = node.token_unbonding_amount.min(node.staked_tokens);
+    node.last_slash_ts = now;
+    node.record_slash(SlashRecord {
+        reason,
+        evidence_hash,
+        amount: slashed,
+        timestamp: now,
+    });
+    if node.total_stake(state.token_stake_rate) < state.min_stake_lamports {
+        node.active = false;
+    }
+
+    emit!(NodeSlashed {
+        node: node.key(),
+        amount: slashed,
+        reason,
+        evidence_hash,
+        remaining_stake: node.stake_lamports,
+        deactivated: !node.active,
+        delegator_amount: delegator_slashed,
+        tokens: tokens_slashed,
+    });
+    Ok(slashed)
+}
+
+/// BN254 base field modulus q (big-endian)
+const BN254_BASE_MODULUS: [u8; 32] = [
+    0x30, 0x64, 0x4e, 0x72, 0xe1, 0x31, 0xa0, 0x29, 0xb8, 0x50, 0x45, 0xb6, 0x81, 0x81, 0x58, 0x5d,
+    0x97, 0x81, 0x6a, 0x91, 0x68, 0x71, 0xca, 0x8d, 0x3c, 0x20, 0x8c, 0x16, 0xd8, 0x7c, 0xfd, 0x47,
+];
+
+/// BN254 scalar field modulus r (big-endian)
+const BN254_SCALAR_MODULUS: [u8; 32] = [
+    0x30, 0x64, 0x4e, 0x72, 0xe1, 0x31, 0xa0, 0x29, 0xb8, 0x50, 0x45, 0xb6, 0x81, 0x81, 0x58, 0x5d,
+    0x28, 0x33, 0xe8, 0x48, 0x79, 0xb9, 0x70, 0x91, 0x43, 0xe1, 0xf5, 0x93, 0xf0, 0x00, 0x00, 0x01,
+];
+
+/// Whether a big-endian value is a canonical BN254 scalar (< r)
+pub fn is_scalar_field_element(value: &[u8; 32]) -> bool {
+    value < &BN254_SCALAR_MODULUS
+}
+
+/// Session nonce public input: the first 31 bytes of the session address,
+/// which always fits in the scalar field
+pub fn session_nonce_input(session: &Pubkey) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    out[1..].copy_from_slice(&session.to_bytes()[..31]);
+    out
+}
+
+/// Negate a G1 point (x, y) -> (x, q - y)
+fn negate_g1(point: &[u8; 64]) -> [u8; 64] {
+    let mut out = *point;
+    if point[32..].iter().all(|b| *b == 0) {
+        return out;
+    }
+    let mut borrow = 0i16;
+    for i in (0..32).rev() {
+        let diff = BN254_BASE_MODULUS[i] as i16 - point[32 + i] as i16 - borrow;
+        borrow = if diff < 0 { 1 } else { 0 };
+        out[32 + i] = (diff + (borrow << 8)) as u8;
+    }
+    out
+}
+
+/// Verify a Groth16 proof: e(-A, B) * e(alpha, beta) * e(vk_x, gamma) * e(C, delta) == 1
+pub fn groth16_verify(
+    vk: &VerifyingKey,
+    proof_a: &[u8; 64],
+    proof_b: &[u8; 128],
+    proof_c: &[u8; 64],
+    public_inputs: &[[u8; 32]; VerifyingKey::IC_LEN - 1],
+) -> Result<()> {
+    // vk_x = IC[0] + sum(input_i * IC[i + 1])
+    let mut vk_x = vk.ic[0];
+    for (input, ic) in public_inputs.iter().zip(vk.ic[1..].iter()) {
+        require!(is_scalar_field_element(input), DvpnError::InvalidProof);
+        let mul_input = [&ic[..], &input[..]].concat();
+        let term = alt_bn128_multiplication(&mul_input).map_err(|_| DvpnError::InvalidProof)?;
+        let add_input = [&vk_x[..], &term[..]].concat();
+        let sum = alt_bn128_addition(&add_input).map_err(|_| DvpnError::InvalidProof)?;
+        vk_x.copy_from_slice(&sum);
+    }
+
+    let pairing_input = [
+        &negate_g1(proof_a)[..],
+        &proof_b[..],
+        &vk.alpha_g1[..],
+        &vk.beta_g2[..],
+        &vk_x[..],
+        &vk.gamma_g2[..],
+        &proof_c[..],
+        &vk.delta_g2[..],
+    ]
+    .concat();
+    let result = alt_bn128_pairing(&pairing_input).map_err(|_| DvpnError::InvalidProof)?;
+    require!(result.last() == Some(&1), DvpnError::InvalidProof);
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(bump: u8)]
+pub struct InitializeState<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + State::SIZE,
+        seeds = [b"state", authority.key().as_ref()],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    /// PDA signer derived from state
+    /// CHECK: PDA without data
+    #[account(seeds = [b"state", authority.key().as_ref()], bump = bump)]
+    pub state_signer: UncheckedAccount<'info>,
+    pub dvpn_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Treasury::SIZE,
+        seeds = [b"treasury", state.key().as_ref()],
+        bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateState<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    /// CHECK: may still have a pre-migration layout; owner and seeds are checked here,
+    /// discriminator and authority in the handler
+    #[account(mut, owner = crate::ID, seeds = [b"state", authority.key().as_ref()], bump)]
+    pub state: UncheckedAccount<'info>,
+    /// Created here for states that predate the treasury
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + Treasury::SIZE,
+        seeds = [b"treasury", state.key().as_ref()],
+        bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateNode<'info> {
+    #[account(mut)]
+    pub operator: Signer<'info>,
+    /// CHECK: may still have a pre-migration layout; owner and seeds are checked here,
+    /// discriminator and operator in the handler
+    #[account(mut, owner = crate::ID, seeds = [b"node", operator.key().as_ref()], bump)]
+    pub node: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeAuthority<'info> {
+    pub admin: Signer<'info>,
+    #[account(mut, seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    pub new_admin: Signer<'info>,
+    #[account(mut, seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+}
+
+#[derive(Accounts)]
+pub struct SetRole<'info> {
+    pub admin: Signer<'info>,
+    #[account(mut, seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+}
+
+#[derive(Accounts)]
+pub struct SetPaused<'info> {
+    pub pauser: Signer<'info>,
+    #[account(mut, seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+}
+
+#[derive(Accounts)]
+pub struct SetNodePaused<'info> {
+    pub pauser: Signer<'info>,
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut)]
+    pub node: Account<'info, Node>,
+}
+
+#[derive(Accounts)]
+pub struct QueueProposal<'info> {
+    #[account(mut)]
+    pub proposer: Signer<'info>,
+    #[account(mut, seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        init,
+        payer = proposer,
+        space = 8 + Proposal::SIZE,
+        seeds = [b"proposal", state.key().as_ref(), &state.next_proposal_id.to_le_bytes()],
+        bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteProposal<'info> {
+    #[account(mut, seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut, seeds = [b"attestors", state.key().as_ref()], bump = attestor_registry.bump)]
+    pub attestor_registry: Account<'info, AttestorRegistry>,
+    #[account(
+        mut,
+        seeds = [b"proposal", state.key().as_ref(), &proposal.id.to_le_bytes()],
+        bump = proposal.bump,
+        has_one = state
+    )]
+    pub proposal: Account<'info, Proposal>,
+    /// Current `state.fee_treasury`; `SetMint` requires it, and empty, once one is set
+    #[account(address = state.fee_treasury @ DvpnError::InvalidTreasury)]
+    pub fee_treasury: Option<InterfaceAccount<'info, TokenAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct CancelProposal<'info> {
+    pub authority: Signer<'info>,
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"proposal", state.key().as_ref(), &proposal.id.to_le_bytes()],
+        bump = proposal.bump,
+        has_one = state
+    )]
+    pub proposal: Account<'info, Proposal>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeAttestorRegistry<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + AttestorRegistry::SIZE,
+        seeds = [b"attestors", state.key().as_ref()],
+        bump
+    )]
+    pub attestor_registry: Account<'info, AttestorRegistry>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterNode<'info> {
+    #[account(mut)]
+    pub operator: Signer<'info>,
+    #[account(mut, seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        init,
+        payer = operator,
+        space = 8 + Node::SIZE,
+        seeds = [b"node", operator.key().as_ref()],
+        bump
+    )]
+    pub node: Account<'info, Node>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RecordUsage<'info> {
+    /// Attestor authorized by the registry; co-signers go in remaining accounts
+    #[account(mut)]
+    pub attestor: Signer<'info>,
+    #[account(mut, seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut)]
+    pub node: Account<'info, Node>,
+    #[account(seeds = [b"attestors", state.key().as_ref()], bump = attestor_registry.bump)]
+    pub attestor_registry: Account<'info, AttestorRegistry>,
+    #[account(
+        mut,
+        seeds = [b"epoch", state.key().as_ref(), &epoch.id.to_le_bytes()],
+        bump = epoch.bump,
+        has_one = state
+    )]
+    pub epoch: Account<'info, Epoch>,
+    #[account(
+        init_if_needed,
+        payer = attestor,
+        space = 8 + NodeEpoch::SIZE,
+        seeds = [b"node_epoch", node.key().as_ref(), &epoch.id.to_le_bytes()],
+        bump
+    )]
+    pub node_epoch: Account<'info, NodeEpoch>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SimulateRewards<'info> {
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    pub node: Account<'info, Node>,
+}
+
+#[derive(Accounts)]
+pub struct StartEpoch<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(mut, seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(constraint = dvpn_mint.key() == state.mint)]
+    pub dvpn_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + Epoch::SIZE,
+        seeds = [b"epoch", state.key().as_ref(), &state.next_epoch_id.to_le_bytes()],
+        bump
+    )]
+    pub epoch: Account<'info, Epoch>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimRewards<'info> {
+    #[account(mut)]
+    pub operator: Signer<'info>,
+    #[account(mut, seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    /// CHECK: PDA signer
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state_signer: UncheckedAccount<'info>,
+    #[account(mut, constraint = dvpn_mint.key() == state.mint)]
+    pub dvpn_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, seeds = [b"node", operator.key().as_ref()], bump)]
+    pub node: Account<'info, Node>,
+    #[account(
+        mut,
+        associated_token::mint = dvpn_mint,
+        associated_token::authority = operator,
+        associated_token::token_program = token_program
+    )]
+    pub operator_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct Faucet<'info> {
+    #[account(mut)]
+    pub attestor: Signer<'info>,
+    #[account(mut, seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(seeds = [b"attestors", state.key().as_ref()], bump = attestor_registry.bump)]
+    pub attestor_registry: Account<'info, AttestorRegistry>,
+    /// CHECK: PDA signer (mint authority)
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state_signer: UncheckedAccount<'info>,
+    #[account(mut, constraint = dvpn_mint.key() == state.mint)]
+    pub dvpn_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut)]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct StartSession<'info> {
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + SessionCounter::SIZE,
+        seeds = [b"session_counter", user.key().as_ref()],
+        bump
+    )]
+    pub session_counter: Account<'info, SessionCounter>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + Session::SIZE,
+        seeds = [
+            b"session",
+            user.key().as_ref(),
+            node.key().as_ref(),
+            &session_counter.next_session_id.to_le_bytes()
+        ],
+        bump
+    )]
+    pub session: Account<'info, Session>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(mut)]
+    pub node: Account<'info, Node>,
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(constraint = state.accepts_payment_mint(&payment_mint.key()) @ DvpnError::PaymentMintNotAccepted)]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, token::mint = payment_mint, token::authority = user)]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    /// Escrow owned by the session PDA
+    #[account(
+        init,
+        payer = user,
+        associated_token::mint = payment_mint,
+        associated_token::authority = session,
+        associated_token::token_program = token_program
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelSession<'info> {
+    pub user: Signer<'info>,
+    #[account(mut, has_one = user, has_one = node)]
+    pub session: Account<'info, Session>,
+    #[account(mut)]
+    pub node: Account<'info, Node>,
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(address = session.payment_mint)]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = session,
+        associated_token::token_program = token_program
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = payment_mint, token::authority = user)]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct RateNode<'info> {
+    pub user: Signer<'info>,
+    #[account(mut, has_one = user, has_one = node)]
+    pub session: Account<'info, Session>,
+    #[account(mut)]
+    pub node: Account<'info, Node>,
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+}
+
+#[derive(Accounts)]
+pub struct CloseSession<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(mut, has_one = user, close = user)]
+    pub session: Account<'info, Session>,
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    /// Mutable so Token-2022 transfer fees withheld in the escrow can be harvested
+    #[account(mut, address = session.payment_mint)]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = session,
+        associated_token::token_program = token_program
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = payment_mint, token::authority = user)]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct TopUpSession<'info> {
+    pub user: Signer<'info>,
+    #[account(mut, has_one = user)]
+    pub session: Account<'info, Session>,
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut, token::mint = payment_mint, token::authority = user)]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = session.payment_mint)]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = session,
+        associated_token::token_program = token_program
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitUsage<'info> {
+    pub attestor: Signer<'info>,
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(seeds = [b"attestors", state.key().as_ref()], bump = attestor_registry.bump)]
+    pub attestor_registry: Account<'info, AttestorRegistry>,
+    #[account(mut, has_one = user)]
+    pub session: Account<'info, Session>,
+    /// CHECK: Session user for validation
+    pub user: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RequestSettlement<'info> {
+    #[account(mut, has_one = node)]
+    pub session: Account<'info, Session>,
+    pub node: Account<'info, Node>,
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(seeds = [b"attestors", state.key().as_ref()], bump = attestor_registry.bump)]
+    pub attestor_registry: Account<'info, AttestorRegistry>,
+    /// Session user, node operator or a registered attestor
+    #[account(
+        constraint = authority.key() == session.user
+            || authority.key() == node.operator
+            || attestor_registry.is_attestor(&authority.key()) @ DvpnError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ForceSettleExpired<'info> {
+    #[account(mut, has_one = node)]
+    pub session: Account<'info, Session>,
+    pub node: Account<'info, Node>,
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    /// Anyone may crank an expired session
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DisputeSession<'info> {
+    pub user: Signer<'info>,
+    #[account(mut, has_one = user)]
+    pub session: Account<'info, Session>,
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(address = session.payment_mint)]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = session,
+        associated_token::token_program = token_program
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = payment_mint, token::authority = user)]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SettleSession<'info> {
+    #[account(mut, has_one = node, has_one = user)]
+    pub session: Account<'info, Session>,
+    #[account(mut)]
+    pub node: Account<'info, Node>,
+    /// CHECK: Session user
+    pub user: UncheckedAccount<'info>,
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(address = session.payment_mint)]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = session,
+        associated_token::token_program = token_program
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = payment_mint, token::authority = node.operator)]
+    pub node_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = payment_mint, token::authority = user)]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, seeds = [b"treasury", state.key().as_ref()], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+    /// Required once `state.fee_treasury` is set; until then no protocol fee is charged
+    #[account(
+        mut,
+        address = state.fee_account(&payment_mint.key(), &treasury.key(), &token_program.key()) @ DvpnError::InvalidTreasury
+    )]
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SettleWithAttestation<'info> {
+    #[account(mut, has_one = node, has_one = user)]
+    pub session: Account<'info, Session>,
+    #[account(mut)]
+    pub node: Account<'info, Node>,
+    /// CHECK: Session user
+    pub user: UncheckedAccount<'info>,
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(seeds = [b"attestors", state.key().as_ref()], bump = attestor_registry.bump)]
+    pub attestor_registry: Account<'info, AttestorRegistry>,
+    #[account(address = session.payment_mint)]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = session,
+        associated_token::token_program = token_program
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = payment_mint, token::authority = node.operator)]
+    pub node_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = payment_mint, token::authority = user)]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, seeds = [b"treasury", state.key().as_ref()], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+    /// Required once `state.fee_treasury` is set; until then no protocol fee is charged
+    #[account(
+        mut,
+        address = state.fee_account(&payment_mint.key(), &treasury.key(), &token_program.key()) @ DvpnError::InvalidTreasury
+    )]
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Interface<'info, TokenInterface>,
+    /// CHECK: Instructions sysvar, used to introspect the Ed25519 instruction
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SettleWithReceipt<'info> {
+    #[account(address = node.operator @ DvpnError::Unauthorized)]
+    pub operator: Signer<'info>,
+    #[account(mut, has_one = node, has_one = user)]
+    pub session: Account<'info, Session>,
+    #[account(mut)]
+    pub node: Account<'info, Node>,
+    /// CHECK: Session user
+    pub user: UncheckedAccount<'info>,
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(address = session.payment_mint)]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = session,
+        associated_token::token_program = token_program
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = payment_mint, token::authority = node.operator)]
+    pub node_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = payment_mint, token::authority = user)]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, seeds = [b"treasury", state.key().as_ref()], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+    /// Required once `state.fee_treasury` is set; until then no protocol fee is charged
+    #[account(
+        mut,
+        address = state.fee_account(&payment_mint.key(), &treasury.key(), &token_program.key()) @ DvpnError::InvalidTreasury
+    )]
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Interface<'info, TokenInterface>,
+    /// CHECK: Instructions sysvar, used to introspect the Ed25519 instruction
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetVerifyingKey<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"proposal", state.key().as_ref(), &proposal.id.to_le_bytes()],
+        bump = proposal.bump,
+        has_one = state
+    )]
+    pub proposal: Account<'info, Proposal>,
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + VerifyingKey::SIZE,
+        seeds = [b"verifying_key", state.key().as_ref()],
+        bump
+    )]
+    pub verifying_key: Account<'info, VerifyingKey>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetNodeCommitment<'info> {
+    pub operator: Signer<'info>,
+    #[account(mut, seeds = [b"node", operator.key().as_ref()], bump)]
+    pub node: Account<'info, Node>,
+}
+
+#[derive(Accounts)]
+pub struct SettleWithProof<'info> {
+    #[account(address = node.operator @ DvpnError::Unauthorized)]
+    pub operator: Signer<'info>,
+    #[account(mut, has_one = node, has_one = user)]
+    pub session: Account<'info, Session>,
+    #[account(mut)]
+    pub node: Account<'info, Node>,
+    /// CHECK: Session user
+    pub user: UncheckedAccount<'info>,
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(seeds = [b"verifying_key", state.key().as_ref()], bump = verifying_key.bump)]
+    pub verifying_key: Account<'info, VerifyingKey>,
+    #[account(address = session.payment_mint)]
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = payment_mint,
+        associated_token::authority = session,
+        associated_token::token_program = token_program
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = payment_mint, token::authority = node.operator)]
+    pub node_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = payment_mint, token::authority = user)]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, seeds = [b"treasury", state.key().as_ref()], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+    /// Required once `state.fee_treasury` is set; until then no protocol fee is charged
+    #[account(
+        mut,
+        address = state.fee_account(&payment_mint.key(), &treasury.key(), &token_program.key()) @ DvpnError::InvalidTreasury
+    )]
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Interface<'info, TokenInterface>,
+    /// CHECK: Instructions sysvar, used to introspect the Ed25519 instruction
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Heartbeat<'info> {
+    /// Node operator or a registered attestor
+    pub signer: Signer<'info>,
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(seeds = [b"attestors", state.key().as_ref()], bump = attestor_registry.bump)]
+    pub attestor_registry: Account<'info, AttestorRegistry>,
+    #[account(mut)]
+    pub node: Account<'info, Node>,
+}
+
+#[derive(Accounts)]
+pub struct MarkInactive<'info> {
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut)]
+    pub node: Account<'info, Node>,
+}
+
+#[derive(Accounts)]
+pub struct NodeOperator<'info> {
+    pub operator: Signer<'info>,
+    #[account(mut, seeds = [b"node", operator.key().as_ref()], bump, has_one = operator)]
+    pub node: Account<'info, Node>,
+}
+
+#[derive(Accounts)]
+pub struct SetPaymentRate<'info> {
+    pub operator: Signer<'info>,
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut, seeds = [b"node", operator.key().as_ref()], bump, has_one = operator)]
+    pub node: Account<'info, Node>,
+}
+
+#[derive(Accounts)]
+pub struct CloseNode<'info> {
+    #[account(mut)]
+    pub operator: Signer<'info>,
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut, seeds = [b"node", operator.key().as_ref()], bump, has_one = operator, close = operator)]
+    pub node: Account<'info, Node>,
+    /// CHECK: Token stake vault PDA; closed if `stake_tokens` ever created it, otherwise empty
+    #[account(mut, seeds = [b"node_vault", node.key().as_ref()], bump)]
+    pub vault: UncheckedAccount<'info>,
+    /// Mint of the vault; required if the vault exists. Mutable so withheld Token-2022 fees can be harvested
+    #[account(mut)]
+    pub vault_mint: Option<InterfaceAccount<'info, Mint>>,
+    /// Receives tokens left in the vault; required if it isn't empty
+    #[account(mut, token::authority = operator)]
+    pub operator_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct ReactivateNode<'info> {
+    pub operator: Signer<'info>,
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut, seeds = [b"node", operator.key().as_ref()], bump, has_one = operator)]
+    pub node: Account<'info, Node>,
+}
+
+#[derive(Accounts)]
+pub struct DelegateStake<'info> {
+    #[account(mut)]
+    pub delegator: Signer<'info>,
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut, seeds = [b"node", node.operator.as_ref()], bump)]
+    pub node: Account<'info, Node>,
+    #[account(
+        init_if_needed,
+        payer = delegator,
+        space = 8 + Delegation::SIZE,
+        seeds = [b"delegation", node.key().as_ref(), delegator.key().as_ref()],
         bump
     )]
+    pub delegation: Account<'info, Delegation>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UndelegateStake<'info> {
+    pub delegator: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"delegation", delegation.node.as_ref(), delegator.key().as_ref()],
+        bump = delegation.bump,
+        has_one = delegator
+    )]
+    pub delegation: Account<'info, Delegation>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawDelegation<'info> {
+    #[account(mut)]
+    pub delegator: Signer<'info>,
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
     pub state: Account<'info, State>,
-    /// PDA signer derived from state
-    /// CHECK: PDA without data
-    #[account(seeds = [b"state", authority.key().as_ref()], bump = bump)]
+    #[account(mut, seeds = [b"node", node.operator.as_ref()], bump)]
+    pub node: Account<'info, Node>,
+    #[account(
+        mut,
+        seeds = [b"delegation", node.key().as_ref(), delegator.key().as_ref()],
+        bump = delegation.bump,
+        has_one = node,
+        has_one = delegator
+    )]
+    pub delegation: Account<'info, Delegation>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimDelegatorRewards<'info> {
+    #[account(mut)]
+    pub delegator: Signer<'info>,
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    /// CHECK: PDA signer
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
     pub state_signer: UncheckedAccount<'info>,
-    pub dvpn_mint: Account<'info, Mint>,
+    #[account(mut, constraint = dvpn_mint.key() == state.mint)]
+    pub dvpn_mint: InterfaceAccount<'info, Mint>,
+    #[account(seeds = [b"node", node.operator.as_ref()], bump)]
+    pub node: Account<'info, Node>,
+    #[account(
+        mut,
+        seeds = [b"delegation", node.key().as_ref(), delegator.key().as_ref()],
+        bump = delegation.bump,
+        has_one = node,
+        has_one = delegator
+    )]
+    pub delegation: Account<'info, Delegation>,
+    #[account(
+        mut,
+        associated_token::mint = dvpn_mint,
+        associated_token::authority = delegator,
+        associated_token::token_program = token_program
+    )]
+    pub delegator_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct StakeTokens<'info> {
+    #[account(mut)]
+    pub operator: Signer<'info>,
+    #[account(mut, seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut, seeds = [b"node", operator.key().as_ref()], bump, has_one = operator)]
+    pub node: Account<'info, Node>,
+    #[account(address = state.mint)]
+    pub dvpn_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        init_if_needed,
+        payer = operator,
+        token::mint = dvpn_mint,
+        token::authority = node,
+        seeds = [b"node_vault", node.key().as_ref()],
+        bump
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::mint = dvpn_mint, token::authority = operator)]
+    pub operator_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawTokens<'info> {
+    pub operator: Signer<'info>,
+    #[account(mut, seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut, seeds = [b"node", operator.key().as_ref()], bump, has_one = operator)]
+    pub node: Account<'info, Node>,
+    #[account(address = state.mint)]
+    pub dvpn_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, seeds = [b"node_vault", node.key().as_ref()], bump)]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, token::authority = operator)]
+    pub operator_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SweepSlashedTokens<'info> {
+    #[account(mut, seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut, seeds = [b"node", node.operator.as_ref()], bump)]
+    pub node: Account<'info, Node>,
+    #[account(address = state.mint)]
+    pub dvpn_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, seeds = [b"node_vault", node.key().as_ref()], bump)]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = state.fee_treasury @ DvpnError::InvalidTreasury)]
+    pub treasury_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeTreasury<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Treasury::SIZE,
+        seeds = [b"treasury", state.key().as_ref()],
+        bump
+    )]
+    pub treasury: Account<'info, Treasury>,
     pub system_program: Program<'info, System>,
 }
 
-#[derive(Accounts)]
-pub struct SetAttestor<'info> {
-    pub authority: Signer<'info>,
-    #[account(mut, seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
-    pub state: Account<'info, State>,
+#[derive(Accounts)]
+pub struct SetFeeConfig<'info> {
+    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(seeds = [b"treasury", state.key().as_ref()], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+    #[account(token::mint = state.mint, token::authority = treasury)]
+    pub treasury_token_account: InterfaceAccount<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawTreasury<'info> {
+    pub authority: Signer<'info>,
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(mut, seeds = [b"treasury", state.key().as_ref()], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        address = state.fee_account(&mint.key(), &treasury.key(), &token_program.key()) @ DvpnError::InvalidTreasury
+    )]
+    pub treasury_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub destination: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SetMinStake<'info> {
+    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+}
+
+#[derive(Accounts)]
+pub struct SlashNode<'info> {
+    pub authority: Signer<'info>,
+    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
+    pub state: Account<'info, State>,
+    #[account(seeds = [b"attestors", state.key().as_ref()], bump = attestor_registry.bump)]
+    pub attestor_registry: Account<'info, AttestorRegistry>,
+    #[account(mut)]
+    pub node: Account<'info, Node>,
+    #[account(mut, seeds = [b"treasury", state.key().as_ref()], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+}
+
+#[account]
+pub struct State {
+    /// Creator key used in the state PDA seeds; never changes. Permissions live in the role fields.
+    pub authority: Pubkey,
+    /// Bootstrap attestor copied into the registry; privileged paths check `AttestorRegistry`
+    pub attestor: Pubkey,
+    pub mint: Pubkey,
+    /// Unused since rewards moved to epoch budgets; kept for the account layout
+    pub reward_rate_bps: u16,
+    pub bump: u8,
+    /// Nodes whose stake drops below this after a slash are deactivated
+    pub min_stake_lamports: u64,
+    /// Share of each settlement payout routed to `fee_treasury`
+    pub protocol_fee_bps: u16,
+    /// Token account (owned by the treasury PDA) that receives protocol fees in `mint`.
+    /// Fees in other payment mints go to the treasury PDA's associated token account.
+    pub fee_treasury: Pubkey,
+    /// Controls protocol parameters and role assignment; transferred via propose/accept
+    pub admin: Pubkey,
+    pub pending_admin: Pubkey,
+    pub pauser: Pubkey,
+    pub fee_manager: Pubkey,
+    pub attestor_manager: Pubkey,
+    /// Global emergency stop, toggled by `pauser`
+    pub paused: bool,
+    /// Delay between queueing and executing a governance proposal
+    pub timelock_delay_secs: i64,
+    /// Id used in the seeds of the next proposal PDA
+    pub next_proposal_id: u64,
+    /// Cap on the mint's total supply, in base units
+    pub max_supply: u64,
+    /// Everything the program has committed to mint: epoch emission budgets, faucet mints and
+    /// legacy node rewards as they are claimed. Never exceeds `max_supply`.
+    pub emission_allocated: u64,
+    pub next_epoch_id: u64,
+    /// Epochs may not overlap; the next one starts at or after this
+    pub last_epoch_end: i64,
+    pub reward_params: RewardParams,
+    /// Time after `settle_session` during which the user may dispute usage
+    pub dispute_window_secs: i64,
+    /// Tokens (base units) a user posts to dispute; forfeited to the node if they lose
+    pub dispute_bond: u64,
+    /// Stake slashed from a node that loses a dispute
+    pub dispute_slash_lamports: u64,
+    /// Nodes without a heartbeat for longer than this refuse sessions and can be marked inactive
+    pub heartbeat_staleness_secs: i64,
+    /// Stake credit, in lamports, per `TOKEN_STAKE_RATE_SCALE` base units of token stake
+    pub token_stake_rate: u64,
+    /// Mints sessions may be paid in; empty slots are `Pubkey::default()`
+    pub payment_mints: [Pubkey; MAX_PAYMENT_MINTS],
+    /// Token stake, including slashed tokens not yet swept, held across all node vaults
+    pub total_vault_tokens: u64,
+}
+
+impl State {
+    pub const SIZE: usize = 32 + 32 + 32 + 2 + 1 + 8 + 2 + 32 + 32 * 5 + 1 + 8 + 8 + 8 + 8 + 8 + 8
+        + RewardParams::SIZE + 8 + 8 + 8 + 8 + 8 + 32 * MAX_PAYMENT_MINTS + 8;
+
+    pub fn accepts_payment_mint(&self, mint: &Pubkey) -> bool {
+        *mint != Pubkey::default() && self.payment_mints.contains(mint)
+    }
+
+    pub fn add_payment_mint(&mut self, mint: Pubkey) -> Result<()> {
+        require!(mint != Pubkey::default(), DvpnError::PaymentMintNotAccepted);
+        require!(!self.accepts_payment_mint(&mint), DvpnError::PaymentMintExists);
+        let slot = self
+            .payment_mints
+            .iter_mut()
+            .find(|m| **m == Pubkey::default())
+            .ok_or(DvpnError::TooManyPaymentMints)?;
+        *slot = mint;
+        Ok(())
+    }
+
+    /// Open sessions in a removed mint still settle; only new sessions are refused
+    pub fn remove_payment_mint(&mut self, mint: &Pubkey) -> Result<()> {
+        require!(*mint != Pubkey::default(), DvpnError::PaymentMintNotFound);
+        let slot = self
+            .payment_mints
+            .iter_mut()
+            .find(|m| *m == mint)
+            .ok_or(DvpnError::PaymentMintNotFound)?;
+        *slot = Pubkey::default();
+        Ok(())
+    }
+
+    /// Protocol fee charged on settlements. Zero until `set_fee_treasury` configures where
+    /// fees go, so sessions keep settling (with the node taking the full payout) before then.
+    pub fn effective_fee_bps(&self) -> u16 {
+        if self.fee_treasury == Pubkey::default() {
+            0
+        } else {
+            self.protocol_fee_bps
+        }
+    }
+
+    /// Token account protocol fees in `mint` are paid into: `fee_treasury` for `state.mint`,
+    /// otherwise the treasury PDA's associated token account for that mint
+    pub fn fee_account(&self, mint: &Pubkey, treasury: &Pubkey, token_program: &Pubkey) -> Pubkey {
+        if *mint == self.mint {
+            self.fee_treasury
+        } else {
+            get_associated_token_address_with_program_id(treasury, mint, token_program)
+        }
+    }
+}
+
+/// Capacity of `State::payment_mints` and `Node::payment_rates`
+pub const MAX_PAYMENT_MINTS: usize = 4;
+
+pub const STAKE_TIER_COUNT: usize = 4;
+
+/// Nodes staking at least `min_stake_lamports` get `multiplier_bps`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StakeTier {
+    pub min_stake_lamports: u64,
+    pub multiplier_bps: u16,
+}
+
+impl StakeTier {
+    pub const SIZE: usize = 8 + 2;
+}
+
+/// Parameters of the reward multiplier applied in `record_usage`:
+/// `stake tier * rating * slash recency`, capped at `max_multiplier_bps`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RewardParams {
+    /// Ascending by `min_stake_lamports`; tiers with a zero multiplier are unused
+    pub stake_tiers: [StakeTier; STAKE_TIER_COUNT],
+    /// Bonus (or penalty) per star of average rating above (below) 3; unrated nodes are neutral
+    pub rating_bps_per_star: u16,
+    /// Penalty right after a slash, decaying linearly to zero over `slash_recovery_secs`
+    pub slash_penalty_bps: u16,
+    pub slash_recovery_secs: i64,
+    pub max_multiplier_bps: u32,
+}
+
+impl RewardParams {
+    pub const SIZE: usize = StakeTier::SIZE * STAKE_TIER_COUNT + 2 + 2 + 8 + 4;
+
+    pub const DEFAULT: RewardParams = RewardParams {
+        stake_tiers: [
+            StakeTier { min_stake_lamports: 0, multiplier_bps: 10_000 },
+            StakeTier { min_stake_lamports: 10_000_000_000, multiplier_bps: 11_000 },
+            StakeTier { min_stake_lamports: 100_000_000_000, multiplier_bps: 12_500 },
+            StakeTier { min_stake_lamports: 1_000_000_000_000, multiplier_bps: 15_000 },
+        ],
+        rating_bps_per_star: 500,
+        slash_penalty_bps: 5_000,
+        slash_recovery_secs: 30 * 24 * 60 * 60,
+        max_multiplier_bps: 20_000,
+    };
+
+    pub fn validate(&self) -> Result<()> {
+        let ascending = self
+            .stake_tiers
+            .windows(2)
+            .all(|w| w[1].multiplier_bps == 0 || w[0].min_stake_lamports <= w[1].min_stake_lamports);
+        require!(ascending, DvpnError::InvalidRewardParams);
+        // Two stars below neutral must not drive the rating factor negative
+        require!(self.rating_bps_per_star <= MAX_BPS / 2, DvpnError::InvalidRewardParams);
+        require!(self.slash_penalty_bps <= MAX_BPS, DvpnError::InvalidRewardParams);
+        require!(self.slash_recovery_secs >= 0, DvpnError::InvalidRewardParams);
+        require!(self.max_multiplier_bps > 0, DvpnError::InvalidRewardParams);
+        Ok(())
+    }
+
+    pub fn stake_multiplier_bps(&self, stake_lamports: u64) -> u32 {
+        self.stake_tiers
+            .iter()
+            .rev()
+            .find(|t| t.multiplier_bps > 0 && stake_lamports >= t.min_stake_lamports)
+            .map_or(MAX_BPS as u32, |t| t.multiplier_bps as u32)
+    }
+
+    pub fn rating_multiplier_bps(&self, rating_sum: u64, rating_count: u32) -> u32 {
+        if rating_count == 0 {
+            return MAX_BPS as u32;
+        }
+        // Average rating in hundredths of a star, offset from the neutral 3 stars
+        let avg_centi = (rating_sum as i128) * 100 / (rating_count as i128);
+        let bonus = (avg_centi - 300) * (self.rating_bps_per_star as i128) / 100;
+        (MAX_BPS as i128 + bonus).max(0) as u32
+    }
+
+    pub fn slash_multiplier_bps(&self, last_slash_ts: i64, now: i64) -> u32 {
+        let elapsed = now.saturating_sub(last_slash_ts);
+        if last_slash_ts == 0 || elapsed >= self.slash_recovery_secs {
+            return MAX_BPS as u32;
+        }
+        let remaining = (self.slash_recovery_secs - elapsed) as i128;
+        let penalty = (self.slash_penalty_bps as i128) * remaining / (self.slash_recovery_secs as i128);
+        (MAX_BPS as i128 - penalty) as u32
+    }
+
+    pub fn multiplier(&self, node: &Node, token_stake_rate: u64, now: i64) -> RewardMultiplier {
+        let stake_bps = self.stake_multiplier_bps(node.total_stake(token_stake_rate));
+        let rating_bps = self.rating_multiplier_bps(node.rating_sum, node.rating_count);
+        let slash_bps = self.slash_multiplier_bps(node.last_slash_ts, now);
+        let bps = MAX_BPS as u128;
+        let total = (stake_bps as u128) * (rating_bps as u128) / bps * (slash_bps as u128) / bps;
+        RewardMultiplier {
+            stake_bps,
+            rating_bps,
+            slash_bps,
+            total_bps: total.min(self.max_multiplier_bps as u128) as u32,
+        }
+    }
+}
+
+/// Breakdown of a node's reward multiplier, all in basis points
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct RewardMultiplier {
+    pub stake_bps: u32,
+    pub rating_bps: u32,
+    pub slash_bps: u32,
+    pub total_bps: u32,
+}
+
+/// Return value of `simulate_rewards`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct RewardSimulation {
+    pub multiplier: RewardMultiplier,
+    pub bytes: u64,
+    pub weighted_bytes: u64,
+}
+
+/// Roles the admin can assign with `set_role`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    Pauser,
+    FeeManager,
+    AttestorManager,
+}
+
+/// Maximum number of attestors in the registry
+pub const MAX_ATTESTORS: usize = 10;
+
+/// Registered attestors and the number of them (M of N) required to approve
+/// usage, settlement, faucet and slashing
+#[account]
+pub struct AttestorRegistry {
+    pub state: Pubkey,
+    pub attestors: Vec<Pubkey>,
+    pub threshold: u8,
+    pub bump: u8,
+}
+
+impl AttestorRegistry {
+    pub const SIZE: usize = 32 + 4 + 32 * MAX_ATTESTORS + 1 + 1;
+
+    pub fn is_attestor(&self, key: &Pubkey) -> bool {
+        self.attestors.contains(key)
+    }
+
+    pub fn add(&mut self, attestor: Pubkey) -> Result<()> {
+        require!(!self.is_attestor(&attestor), DvpnError::AttestorExists);
+        require!(self.attestors.len() < MAX_ATTESTORS, DvpnError::TooManyAttestors);
+        self.attestors.push(attestor);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, attestor: &Pubkey) -> Result<()> {
+        let idx = self
+            .attestors
+            .iter()
+            .position(|a| a == attestor)
+            .ok_or(DvpnError::AttestorNotFound)?;
+        // Never leave the registry unable to reach its threshold
+        require!(self.attestors.len() > self.threshold as usize, DvpnError::InvalidThreshold);
+        self.attestors.remove(idx);
+        Ok(())
+    }
+
+    /// Set how many distinct attestors must approve privileged actions (M of N)
+    pub fn set_threshold(&mut self, threshold: u8) -> Result<()> {
+        require!(
+            threshold > 0 && threshold as usize <= self.attestors.len(),
+            DvpnError::InvalidThreshold
+        );
+        self.threshold = threshold;
+        Ok(())
+    }
+}
+
+/// Parameter changes that must go through the governance timelock
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GovernanceAction {
+    /// Cap on the mint's total supply, in base units
+    SetMaxSupply { max_supply: u64 },
+    SetProtocolFee { protocol_fee_bps: u16 },
+    /// Switch the protocol mint. Only once no token stake is held and the fee treasury is
+    /// empty; the fee treasury is then unset and the payment mint allowlist updated.
+    SetMint { mint: Pubkey },
+    AddAttestor { attestor: Pubkey },
+    RemoveAttestor { attestor: Pubkey },
+    SetAttestorThreshold { threshold: u8 },
+    SetTimelockDelay { delay_secs: i64 },
+    SetRewardParams { params: RewardParams },
+    /// Allow sessions to be paid in `mint`
+    AddPaymentMint { mint: Pubkey },
+    RemovePaymentMint { mint: Pubkey },
+    /// Approve the Groth16 verifying key with this `verifying_key_hash` for `set_verifying_key`
+    SetVerifyingKey { vk_hash: [u8; 32] },
+}
+
+impl GovernanceAction {
+    pub const SIZE: usize = 1 + RewardParams::SIZE;
+
+    /// Key allowed to queue (and cancel) this action
+    pub fn role_holder(&self, state: &State) -> Pubkey {
+        match self {
+            GovernanceAction::SetProtocolFee { .. }
+            | GovernanceAction::AddPaymentMint { .. }
+            | GovernanceAction::RemovePaymentMint { .. } => state.fee_manager,
+            GovernanceAction::AddAttestor { .. }
+            | GovernanceAction::RemoveAttestor { .. }
+            | GovernanceAction::SetAttestorThreshold { .. } => state.attestor_manager,
+            GovernanceAction::SetMaxSupply { .. }
+            | GovernanceAction::SetMint { .. }
+            | GovernanceAction::SetTimelockDelay { .. }
+            | GovernanceAction::SetRewardParams { .. }
+            | GovernanceAction::SetVerifyingKey { .. } => state.admin,
+        }
+    }
+
+    /// Checks that don't depend on state at execution time
+    pub fn validate(&self) -> Result<()> {
+        match *self {
+            GovernanceAction::SetProtocolFee { protocol_fee_bps } => {
+                require!(protocol_fee_bps <= MAX_BPS, DvpnError::InvalidFee)
+            }
+            GovernanceAction::SetAttestorThreshold { threshold } => {
+                require!(threshold > 0, DvpnError::InvalidThreshold)
+            }
+            GovernanceAction::SetTimelockDelay { delay_secs } => require!(
+                (MIN_TIMELOCK_DELAY_SECS..=MAX_TIMELOCK_DELAY_SECS).contains(&delay_secs),
+                DvpnError::InvalidDelay
+            ),
+            GovernanceAction::SetRewardParams { params } => params.validate()?,
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProposalStatus {
+    Queued,
+    Executed,
+    Cancelled,
+    /// An executed `SetVerifyingKey` proposal whose key has been installed
+    Applied,
+}
+
+/// A timelocked governance action
+#[account]
+pub struct Proposal {
+    pub state: Pubkey,
+    pub id: u64,
+    pub proposer: Pubkey,
+    pub action: GovernanceAction,
+    /// Earliest time the proposal can be executed
+    pub eta: i64,
+    pub status: ProposalStatus,
+    pub bump: u8,
+}
+
+impl Proposal {
+    pub const SIZE: usize = 32 + 8 + 32 + GovernanceAction::SIZE + 8 + 1 + 1;
+}
+
+/// A reward period whose budget is split between nodes by bytes relayed
+#[account]
+pub struct Epoch {
+    pub state: Pubkey,
+    pub id: u64,
+    pub start_ts: i64,
+    pub end_ts: i64,
+    /// Tokens (base units) distributed once the epoch ends
+    pub emission_budget: u64,
+    pub total_bytes: u64,
+    /// Sum of multiplier-weighted bytes; the denominator for payouts
+    pub total_weighted_bytes: u64,
+    pub bump: u8,
+}
+
+impl Epoch {
+    pub const SIZE: usize = 32 + 8 + 8 + 8 + 8 + 8 + 8 + 1;
+}
+
+/// Bytes a node relayed in one epoch
+#[account]
+pub struct NodeEpoch {
+    pub node: Pubkey,
+    pub epoch: u64,
+    pub bytes: u64,
+    pub claimed: bool,
+    pub bump: u8,
+    /// `bytes` scaled by the node's reward multiplier at the time of each report
+    pub weighted_bytes: u64,
+}
+
+impl NodeEpoch {
+    pub const SIZE: usize = 32 + 8 + 8 + 1 + 1 + 8;
+}
+
+/// Protocol treasury; holds slashed stake as lamports and owns the fee token account
+#[account]
+pub struct Treasury {
+    pub state: Pubkey,
+    pub total_slashed_lamports: u64,
+    pub bump: u8,
+    pub total_fees_collected: u64,
+    pub total_fees_withdrawn: u64,
+}
+
+impl Treasury {
+    pub const SIZE: usize = 32 + 8 + 1 + 8 + 8;
+}
+
+#[account]
+pub struct Node {
+    pub operator: Pubkey,
+    pub bandwidth_mbps: u32,
+    pub meta_hash: [u8; 32],
+    pub stake_lamports: u64,
+    pub total_bytes_relayed: u64,
+    pub unclaimed_reward: u64,
+    pub wg_pubkey: [u8; 32],
+    pub rating_sum: u64,
+    pub rating_count: u32,
+    pub active: bool,
+    pub registered_at: i64,
+    pub last_slash_ts: i64,
+    /// Commitment bound into bandwidth proofs as a public input
+    pub zk_commitment: [u8; 32],
+    /// Sessions started against this node that are not yet settled
+    pub open_sessions: u32,
+    /// When `request_unstake` was called (0 if not unbonding)
+    pub unbonding_started_at: i64,
+    /// Total number of slashes; `slash_history` keeps the most recent ones
+    pub slash_count: u32,
+    pub slash_history: [SlashRecord; SLASH_HISTORY_LEN],
+    /// Operator-set prices, snapshotted into each session at start
+    pub pricing: PriceSchedule,
+    pub pricing_updated_at: i64,
+    /// Set by the pauser to halt this node without a global pause
+    pub paused: bool,
+    pub last_heartbeat_ts: i64,
+    /// Uptime last reported with a heartbeat
+    pub uptime_secs: u64,
+    /// Deactivated by `mark_inactive`; cleared by the next heartbeat
+    pub marked_stale: bool,
+    /// Cut of delegators' rewards kept by the operator
+    pub commission_bps: u16,
+    /// Lamports delegated to this node, net of slashing; held in the node account
+    pub delegated_lamports: u64,
+    /// Outstanding `Delegation::shares`; each is worth `delegated_lamports / delegation_shares`
+    pub delegation_shares: u64,
+    /// Delegator rewards per share, scaled by `REWARD_PER_SHARE_PRECISION`
+    pub delegator_reward_per_share: u128,
+    /// Protocol tokens locked in the node vault, including any unbonding amount
+    pub staked_tokens: u64,
+    /// Portion of `staked_tokens` queued by `request_token_unstake`
+    pub token_unbonding_amount: u64,
+    pub token_unbonding_started_at: i64,
+    /// Slashed tokens still in the vault, awaiting `sweep_slashed_tokens`
+    pub slashed_tokens: u64,
+    /// Conversion of `pricing` into payment mints other than `state.mint`
+    pub payment_rates: [PaymentRate; MAX_PAYMENT_MINTS],
+    /// `NodeEpoch` records not yet claimed through `claim_rewards`
+    pub unclaimed_epochs: u32,
+    /// Variable-length (its host is a `String`), so it stays last and new fields go before it
+    pub metadata: NodeMetadata,
+}
+
+impl Node {
+    /// Where `metadata` started when it was introduced, right after `paused`
+    pub const LEGACY_METADATA_OFFSET: usize = 8 + 32 + 4 + 32 + 8 + 8 + 8 + 32 + 8 + 4 + 1 + 8 + 8 + 32 + 4 + 8
+        + 4 + SlashRecord::SIZE * SLASH_HISTORY_LEN + PriceSchedule::SIZE + 8 + 1;
+    /// Account data offset of `metadata`, including the discriminator
+    pub const METADATA_OFFSET: usize = Self::LEGACY_METADATA_OFFSET
+        + 8 + 8 + 1 + 2 + 8 + 8 + 16 + 8 + 8 + 8 + 8 + PaymentRate::SIZE * MAX_PAYMENT_MINTS + 4;
+    pub const SIZE: usize = Self::METADATA_OFFSET - 8 + NodeMetadata::SIZE;
+
+    /// Rate the node prices `mint` at; `state.mint` (`reward_mint`) is always accepted at par
+    pub fn payment_rate(&self, mint: &Pubkey, reward_mint: &Pubkey) -> Option<u64> {
+        if mint == reward_mint {
+            return Some(PAYMENT_RATE_SCALE);
+        }
+        self.payment_rates
+            .iter()
+            .find(|r| r.mint == *mint && r.rate > 0)
+            .map(|r| r.rate)
+    }
+
+    /// Set, replace or (with `rate == 0`) clear the rate for `mint`
+    pub fn set_payment_rate(&mut self, mint: Pubkey, rate: u64) -> Result<()> {
+        if let Some(entry) = self.payment_rates.iter_mut().find(|r| r.mint == mint) {
+            entry.rate = rate;
+            if rate == 0 {
+                *entry = PaymentRate::default();
+            }
+            return Ok(());
+        }
+        if rate == 0 {
+            return Ok(());
+        }
+        let slot = self
+            .payment_rates
+            .iter_mut()
+            .find(|r| r.mint == Pubkey::default())
+            .ok_or(DvpnError::TooManyPaymentMints)?;
+        *slot = PaymentRate { mint, rate };
+        Ok(())
+    }
+
+    /// Lamports of stake credit for `staked_tokens` at `token_stake_rate`
+    pub fn token_stake_credit(&self, token_stake_rate: u64) -> u64 {
+        let credit = (self.staked_tokens as u128) * (token_stake_rate as u128) / TOKEN_STAKE_RATE_SCALE;
+        credit.min(u64::MAX as u128) as u64
+    }
+
+    /// Operator stake plus delegated stake plus token stake credit
+    pub fn total_stake(&self, token_stake_rate: u64) -> u64 {
+        self.stake_lamports
+            .saturating_add(self.delegated_lamports)
+            .saturating_add(self.token_stake_credit(token_stake_rate))
+    }
+
+    /// Shares minted for a delegation of `lamports` at the current share price
+    pub fn shares_for(&self, lamports: u64) -> Result<u64> {
+        if self.delegation_shares == 0 {
+            return Ok(lamports);
+        }
+        // Shares of a fully slashed pool are worthless; don't let new delegators buy into them
+        require!(self.delegated_lamports > 0, DvpnError::InvalidDelegation);
+        let shares = (lamports as u128) * (self.delegation_shares as u128) / (self.delegated_lamports as u128);
+        u64::try_from(shares).map_err(|_| error!(DvpnError::MathOverflow))
+    }
+
+    /// Lamports `shares` currently redeem for
+    pub fn shares_value(&self, shares: u64) -> u64 {
+        if self.delegation_shares == 0 {
+            return 0;
+        }
+        ((shares as u128) * (self.delegated_lamports as u128) / (self.delegation_shares as u128)) as u64
+    }
+
+    /// Split a claimed reward pro rata to stake. The delegators' part, less commission,
+    /// is credited to `delegator_reward_per_share`. Returns `(operator, delegators)`.
+    pub fn distribute_reward(&mut self, amount: u64, token_stake_rate: u64) -> Result<(u64, u64)> {
+        if self.delegation_shares == 0 || self.delegated_lamports == 0 {
+            return Ok((amount, 0));
+        }
+        let gross = (amount as u128) * (self.delegated_lamports as u128) / (self.total_stake(token_stake_rate) as u128);
+        let commission = gross * (self.commission_bps as u128) / (MAX_BPS as u128);
+        let net = gross - commission;
+        self.delegator_reward_per_share = self
+            .delegator_reward_per_share
+            .checked_add(net * REWARD_PER_SHARE_PRECISION / (self.delegation_shares as u128))
+            .ok_or(DvpnError::MathOverflow)?;
+        Ok((amount - net as u64, net as u64))
+    }
+
+    /// No heartbeat within `staleness_secs` of `now`
+    pub fn is_stale(&self, now: i64, staleness_secs: i64) -> bool {
+        now.saturating_sub(self.last_heartbeat_ts) > staleness_secs
+    }
+
+    /// Append to the slash history ring buffer, overwriting the oldest entry
+    pub fn record_slash(&mut self, record: SlashRecord) {
+        let idx = self.slash_count as usize % SLASH_HISTORY_LEN;
+        self.slash_history[idx] = record;
+        self.slash_count = self.slash_count.saturating_add(1);
+    }
+}
+
+/// Token base units that `State::token_stake_rate` is quoted per
+pub const TOKEN_STAKE_RATE_SCALE: u128 = 1_000_000_000;
+
+/// Fixed-point scale of `Node::delegator_reward_per_share`
+pub const REWARD_PER_SHARE_PRECISION: u128 = 1_000_000_000_000;
+
+/// One delegator's stake in a node
+#[account]
+pub struct Delegation {
+    pub node: Pubkey,
+    pub delegator: Pubkey,
+    pub shares: u64,
+    /// Portion of `shares` queued by `undelegate_stake`
+    pub unbonding_shares: u64,
+    pub unbonding_started_at: i64,
+    /// Rewards per share already accounted for, times `shares`
+    pub reward_debt: u128,
+    /// Accrued rewards not yet minted
+    pub pending_reward: u64,
+    pub bump: u8,
+}
+
+impl Delegation {
+    pub const SIZE: usize = 32 + 32 + 8 + 8 + 8 + 16 + 8 + 1;
+
+    fn accumulated(&self, node: &Node) -> u128 {
+        (self.shares as u128) * node.delegator_reward_per_share / REWARD_PER_SHARE_PRECISION
+    }
+
+    /// Move rewards earned since the last update into `pending_reward`
+    pub fn accrue(&mut self, node: &Node) -> Result<()> {
+        let accumulated = self.accumulated(node);
+        let owed = u64::try_from(accumulated.saturating_sub(self.reward_debt)).map_err(|_| error!(DvpnError::MathOverflow))?;
+        self.pending_reward = self.pending_reward.checked_add(owed).ok_or(DvpnError::MathOverflow)?;
+        self.reward_debt = accumulated;
+        Ok(())
+    }
+
+    /// Reset the reward baseline after `shares` changes
+    pub fn sync_reward_debt(&mut self, node: &Node) {
+        self.reward_debt = self.accumulated(node);
+    }
+}
+
+/// Bits of `NodeMetadata::protocols`
+pub const PROTOCOL_WIREGUARD: u16 = 1 << 0;
+pub const PROTOCOL_OPENVPN: u16 = 1 << 1;
+pub const PROTOCOL_IKEV2: u16 = 1 << 2;
+pub const PROTOCOL_SOCKS5: u16 = 1 << 3;
+pub const PROTOCOL_ALL: u16 = PROTOCOL_WIREGUARD | PROTOCOL_OPENVPN | PROTOCOL_IKEV2 | PROTOCOL_SOCKS5;
+
+pub const MAX_ENDPOINT_HOST_LEN: usize = 64;
+
+/// Operator-published connection details, stored as the last `Node` field. `endpoint_host` is
+/// last here too, so the fixed-size fields sit at fixed offsets from `Node::METADATA_OFFSET`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct NodeMetadata {
+    /// ISO 3166-1 alpha-2 country code, uppercase ASCII; zeroed if unset
+    pub region: [u8; 2],
+    pub endpoint_port: u16,
+    /// `PROTOCOL_*` bitmask
+    pub protocols: u16,
+    /// Concurrent session limit enforced by `start_session`; 0 means unlimited
+    pub max_sessions: u32,
+    /// Hostname or IP address, at most `MAX_ENDPOINT_HOST_LEN` bytes
+    pub endpoint_host: String,
+}
+
+impl NodeMetadata {
+    pub const SIZE: usize = 2 + 2 + 2 + 4 + 4 + MAX_ENDPOINT_HOST_LEN;
+
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            self.region == [0; 2] || self.region.iter().all(u8::is_ascii_uppercase),
+            DvpnError::InvalidMetadata
+        );
+        require!(self.protocols & !PROTOCOL_ALL == 0, DvpnError::InvalidMetadata);
+        require!(self.endpoint_host.len() <= MAX_ENDPOINT_HOST_LEN, DvpnError::InvalidMetadata);
+        require!(
+            self.endpoint_host.bytes().all(|b| b.is_ascii_graphic()),
+            DvpnError::InvalidMetadata
+        );
+        Ok(())
+    }
+}
+
+/// Node price schedule. On a node, amounts are base units of `state.mint`; on a
+/// session, base units of its payment mint.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct PriceSchedule {
+    pub price_per_mib: u64,
+    /// Charged per started minute of session time
+    pub price_per_minute: u64,
+    pub min_charge: u64,
+}
+
+impl PriceSchedule {
+    pub const SIZE: usize = 8 + 8 + 8;
+
+    /// The schedule in a payment mint priced at `rate`
+    pub fn convert(&self, rate: u64) -> Result<Self> {
+        Ok(PriceSchedule {
+            price_per_mib: convert_amount(self.price_per_mib, rate)?,
+            price_per_minute: convert_amount(self.price_per_minute, rate)?,
+            min_charge: convert_amount(self.min_charge, rate)?,
+        })
+    }
+}
+
+/// `PaymentRate::rate` and `Session::payment_rate` are quoted per this many base units of `state.mint`
+pub const PAYMENT_RATE_SCALE: u64 = 1_000_000_000;
+
+/// Payment mint base units per `PAYMENT_RATE_SCALE` base units of `state.mint`; covers both
+/// the exchange rate and any difference in decimals
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct PaymentRate {
+    pub mint: Pubkey,
+    pub rate: u64,
+}
+
+impl PaymentRate {
+    pub const SIZE: usize = 32 + 8;
+}
+
+/// Convert an amount of `state.mint` base units into a payment mint at `rate`, rounding down
+pub fn convert_amount(amount: u64, rate: u64) -> Result<u64> {
+    let converted = (amount as u128) * (rate as u128) / (PAYMENT_RATE_SCALE as u128);
+    u64::try_from(converted).map_err(|_| error!(DvpnError::MathOverflow))
+}
+
+/// Number of slash records retained per node
+pub const SLASH_HISTORY_LEN: usize = 8;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SlashReason {
+    #[default]
+    Other,
+    Downtime,
+    InvalidUsage,
+    Misbehavior,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct SlashRecord {
+    pub reason: SlashReason,
+    pub evidence_hash: [u8; 32],
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+impl SlashRecord {
+    pub const SIZE: usize = 1 + 32 + 8 + 8;
+}
+
+/// Per-user counter used to derive a fresh session PDA for every `start_session`
+#[account]
+pub struct SessionCounter {
+    pub user: Pubkey,
+    pub next_session_id: u64,
+    pub bump: u8,
+}
+
+impl SessionCounter {
+    pub const SIZE: usize = 32 + 8 + 1;
+}
+
+#[account]
+pub struct Session {
+    pub user: Pubkey,
+    pub node: Pubkey,
+    pub deposit_amount: u64,
+    pub bytes_used: u64,
+    pub started_at: i64,
+    pub status: SessionStatus,
+    pub bump: u8,
+    /// Highest attestation nonce accepted for this session
+    pub attestation_nonce: u64,
+    /// Node pricing at the time the session started, converted into `payment_mint`
+    pub pricing: PriceSchedule,
+    /// Index from the user's `SessionCounter`, part of the PDA seeds
+    pub session_id: u64,
+    /// Requested lifetime in seconds; usage is not accepted after `expires_at`
+    pub max_duration: i64,
+    pub expires_at: i64,
+    /// User's rating of the node after settlement, 0 until `rate_node`
+    pub rating: u8,
+    /// When usage stopped; session time is billed up to here
+    pub ended_at: i64,
+    /// End of the dispute window opened by `settle_session`; once disputed, the deadline for
+    /// resolving the dispute
+    pub dispute_deadline: i64,
+    /// Byte count claimed by the user in `dispute_session`
+    pub disputed_bytes: u64,
+    pub dispute_bond: u64,
+    /// Mint the deposit was made in; escrow, refunds, payouts and fees all use it
+    pub payment_mint: Pubkey,
+    /// Node's rate for `payment_mint` at start, used to convert the dispute bond
+    pub payment_rate: u64,
+}
+
+impl Session {
+    pub const SIZE: usize = 32 + 32 + 8 + 8 + 8 + 1 + 1 + 8 + PriceSchedule::SIZE + 8 + 8 + 8 + 1 + 8 + 8 + 8 + 8 + 32 + 8;
+
+    /// Attestation-backed settlement applies to live and disputed sessions
+    pub fn accepts_verified_usage(&self) -> bool {
+        matches!(self.status, SessionStatus::Active | SessionStatus::Disputed)
+    }
+
+    /// Seconds of session time chargeable at `now`, never past expiry
+    pub fn billable_secs(&self, now: i64) -> i64 {
+        now.min(self.expires_at).saturating_sub(self.started_at)
+    }
+}
+
+/// Session lifecycle. `Active` and `Settled` keep the byte values of the former
+/// `closed: bool` flag, but `Session` has grown since and has no migration, so sessions
+/// opened under an older program version must be settled or cancelled before upgrading.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionStatus {
+    Active,
+    Settled,
+    /// Settlement requested; waiting out the dispute window
+    Pending,
+    /// User contested the usage; awaiting an attestor quorum or proof, or `expire_dispute`
+    Disputed,
+    Cancelled,
+}
+
+/// Groth16 verifying key for the session bandwidth circuit
+#[account]
+pub struct VerifyingKey {
+    pub alpha_g1: [u8; 64],
+    pub beta_g2: [u8; 128],
+    pub gamma_g2: [u8; 128],
+    pub delta_g2: [u8; 128],
+    pub ic: [[u8; 64]; VerifyingKey::IC_LEN],
+    pub bump: u8,
+}
+
+impl VerifyingKey {
+    /// One IC point per public input (total, session nonce, node commitment, usage commitment)
+    /// plus the constant
+    pub const IC_LEN: usize = 5;
+    pub const SIZE: usize = 64 + 128 * 3 + 64 * Self::IC_LEN + 1;
+}
+
+/// sha256(alpha_g1 || beta_g2 || gamma_g2 || delta_g2 || ic[0] || .. || ic[IC_LEN - 1]),
+/// the hash a `SetVerifyingKey` proposal approves
+pub fn verifying_key_hash(
+    alpha_g1: &[u8; 64],
+    beta_g2: &[u8; 128],
+    gamma_g2: &[u8; 128],
+    delta_g2: &[u8; 128],
+    ic: &[[u8; 64]; VerifyingKey::IC_LEN],
+) -> [u8; 32] {
+    let mut parts: Vec<&[u8]> = vec![alpha_g1, beta_g2, gamma_g2, delta_g2];
+    parts.extend(ic.iter().map(|point| point.as_slice()));
+    hashv(&parts).to_bytes()
+}
+
+#[event]
+pub struct AuthorityProposed {
+    pub current: Pubkey,
+    pub proposed: Pubkey,
+}
+
+#[event]
+pub struct AuthorityTransferred {
+    pub previous: Pubkey,
+    pub admin: Pubkey,
+}
+
+#[event]
+pub struct RoleUpdated {
+    pub role: Role,
+    pub holder: Pubkey,
+}
+
+/// `node` is `None` for the global switch
+#[event]
+pub struct PauseUpdated {
+    pub node: Option<Pubkey>,
+    pub paused: bool,
+}
+
+#[event]
+pub struct SessionStarted {
+    pub session: Pubkey,
+    pub session_id: u64,
+    pub user: Pubkey,
+    pub node: Pubkey,
+    pub deposit: u64,
+    pub expires_at: i64,
+    pub payment_mint: Pubkey,
+}
+
+#[event]
+pub struct SessionCancelled {
+    pub session: Pubkey,
+    pub refund: u64,
+}
+
+#[event]
+pub struct SessionExpired {
+    pub session: Pubkey,
+    pub bytes: u64,
+    pub settled_by: Pubkey,
+}
+
+#[event]
+pub struct SessionClosed {
+    pub session: Pubkey,
+    pub user: Pubkey,
+}
+
+#[event]
+pub struct ReceiptSettled {
+    pub session: Pubkey,
+    pub cumulative_bytes: u64,
+}
+
+#[event]
+pub struct SettlementRequested {
+    pub session: Pubkey,
+    pub bytes: u64,
+    pub dispute_deadline: i64,
+}
+
+#[event]
+pub struct SessionDisputed {
+    pub session: Pubkey,
+    pub node_bytes: u64,
+    pub user_bytes: u64,
+    pub bond: u64,
+}
+
+#[event]
+pub struct DisputeResolved {
+    pub session: Pubkey,
+    pub resolved_bytes: u64,
+    pub node_bytes: u64,
+    pub user_bytes: u64,
+    pub user_won: bool,
+    pub bond: u64,
+    pub slashed: u64,
+}
+
+#[event]
+pub struct DisputeExpired {
+    pub session: Pubkey,
+    pub node_bytes: u64,
+    pub user_bytes: u64,
+    pub bond: u64,
+}
+
+#[event]
+pub struct DisputeConfigUpdated {
+    pub dispute_window_secs: i64,
+    pub dispute_bond: u64,
+    pub dispute_slash_lamports: u64,
+}
+
+#[event]
+pub struct NodeRated {
+    pub node: Pubkey,
+    pub session: Pubkey,
+    pub rating: u8,
+    pub rating_sum: u64,
+    pub rating_count: u32,
+}
+
+#[event]
+pub struct SessionToppedUp {
+    pub session: Pubkey,
+    pub amount: u64,
+    pub deposit: u64,
+}
+
+#[event]
+pub struct UsageSubmitted {
+    pub session: Pubkey,
+    pub bytes: u64,
+}
+
+#[event]
+pub struct SessionSettled {
+    pub session: Pubkey,
+    pub payout: u64,
+    pub protocol_fee: u64,
+    pub refund: u64,
+    pub bytes: u64,
+}
+
+#[event]
+pub struct AttestationVerified {
+    pub session: Pubkey,
+    pub attestors: Vec<Pubkey>,
+    pub total_bytes: u64,
+    pub nonce: u64,
+}
+
+#[event]
+pub struct EpochStarted {
+    pub epoch: Pubkey,
+    pub id: u64,
+    pub start_ts: i64,
+    pub end_ts: i64,
+    pub emission_budget: u64,
+}
+
+#[event]
+pub struct RewardsClaimed {
+    pub node: Pubkey,
+    /// Minted to the operator, including commission
+    pub amount: u64,
+    pub epochs: u32,
+    /// Credited to delegators
+    pub delegator_amount: u64,
+}
+
+#[event]
+pub struct ProposalQueued {
+    pub proposal: Pubkey,
+    pub id: u64,
+    pub action: GovernanceAction,
+    pub eta: i64,
+}
+
+#[event]
+pub struct VerifyingKeyUpdated {
+    pub proposal: Pubkey,
+    pub vk_hash: [u8; 32],
+}
+
+#[event]
+pub struct ProposalExecuted {
+    pub proposal: Pubkey,
+    pub id: u64,
+    pub action: GovernanceAction,
+}
+
+#[event]
+pub struct ProposalCancelled {
+    pub proposal: Pubkey,
+    pub id: u64,
+    pub cancelled_by: Pubkey,
 }
 
-#[derive(Accounts)]
-pub struct SetMint<'info> {
-    pub authority: Signer<'info>,
-    #[account(mut, seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
-    pub state: Account<'info, State>,
+#[event]
+pub struct AttestorAdded {
+    pub attestor: Pubkey,
+    pub count: u8,
 }
 
-#[derive(Accounts)]
-pub struct RegisterNode<'info> {
-    #[account(mut)]
-    pub operator: Signer<'info>,
-    #[account(mut, seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
-    pub state: Account<'info, State>,
-    #[account(
-        init,
-        payer = operator,
-        space = 8 + Node::SIZE,
-        seeds = [b"node", operator.key().as_ref()],
-        bump
-    )]
-    pub node: Account<'info, Node>,
-    pub system_program: Program<'info, System>,
+#[event]
+pub struct AttestorRemoved {
+    pub attestor: Pubkey,
+    pub count: u8,
 }
 
-#[derive(Accounts)]
-pub struct RecordUsage<'info> {
-    /// Attestor authorized by state
-    pub attestor: Signer<'info>,
-    #[account(mut, seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
-    pub state: Account<'info, State>,
-    #[account(mut)]
-    pub node: Account<'info, Node>,
+#[event]
+pub struct AttestorThresholdUpdated {
+    pub threshold: u8,
 }
 
-#[derive(Accounts)]
-pub struct ClaimRewards<'info> {
-    #[account(mut)]
-    pub operator: Signer<'info>,
-    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
-    pub state: Account<'info, State>,
-    /// CHECK: PDA signer
-    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
-    pub state_signer: UncheckedAccount<'info>,
-    #[account(mut, constraint = dvpn_mint.key() == state.mint)]
-    pub dvpn_mint: Account<'info, Mint>,
-    #[account(mut, seeds = [b"node", operator.key().as_ref()], bump)]
-    pub node: Account<'info, Node>,
-    #[account(mut, associated_token::mint = dvpn_mint, associated_token::authority = operator)]
-    pub operator_token_account: Account<'info, TokenAccount>,
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
+#[event]
+pub struct ProofVerified {
+    pub session: Pubkey,
+    pub total_bytes: u64,
 }
 
-#[derive(Accounts)]
-pub struct Faucet<'info> {
-    #[account(mut)]
-    pub attestor: Signer<'info>,
-    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
-    pub state: Account<'info, State>,
-    /// CHECK: PDA signer (mint authority)
-    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
-    pub state_signer: UncheckedAccount<'info>,
-    #[account(mut, constraint = dvpn_mint.key() == state.mint)]
-    pub dvpn_mint: Account<'info, Mint>,
-    #[account(mut)]
-    pub user_token_account: Account<'info, TokenAccount>,
-    pub token_program: Program<'info, Token>,
+#[event]
+pub struct FeeConfigUpdated {
+    pub protocol_fee_bps: u16,
+    pub fee_treasury: Pubkey,
 }
 
-#[derive(Accounts)]
-pub struct StartSession<'info> {
-    #[account(
-        init,
-        payer = user,
-        space = 8 + Session::SIZE,
-        seeds = [b"session", user.key().as_ref(), node.key().as_ref()],
-        bump
-    )]
-    pub session: Account<'info, Session>,
-    #[account(mut)]
-    pub user: Signer<'info>,
-    #[account(mut)]
-    pub node: Account<'info, Node>,
-    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
-    pub state: Account<'info, State>,
-    pub system_program: Program<'info, System>,
+#[event]
+pub struct TreasuryWithdrawn {
+    pub destination: Pubkey,
+    pub amount: u64,
 }
 
-#[derive(Accounts)]
-pub struct SubmitUsage<'info> {
-    pub attestor: Signer<'info>,
-    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
-    pub state: Account<'info, State>,
-    #[account(mut, has_one = user)]
-    pub session: Account<'info, Session>,
-    /// CHECK: Session user for validation
-    pub user: UncheckedAccount<'info>,
+#[event]
+pub struct NodeUpdated {
+    pub node: Pubkey,
+    pub metadata: NodeMetadata,
 }
 
-#[derive(Accounts)]
-pub struct SettleSession<'info> {
-    #[account(mut, has_one = node, has_one = user)]
-    pub session: Account<'info, Session>,
-    #[account(mut)]
-    pub node: Account<'info, Node>,
-    /// CHECK: Session user
-    pub user: UncheckedAccount<'info>,
-    #[account(mut)]
-    pub escrow_token_account: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub node_token_account: Account<'info, TokenAccount>,
-    pub token_program: Program<'info, Token>,
-    pub authority: Signer<'info>,
+#[event]
+pub struct NodePricingUpdated {
+    pub node: Pubkey,
+    pub price_per_mib: u64,
+    pub price_per_minute: u64,
+    pub min_charge: u64,
 }
 
-#[derive(Accounts)]
-pub struct SettleWithAttestation<'info> {
-    #[account(mut, has_one = node, has_one = user)]
-    pub session: Account<'info, Session>,
-    #[account(mut)]
-    pub node: Account<'info, Node>,
-    /// CHECK: Session user
-    pub user: UncheckedAccount<'info>,
-    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
-    pub state: Account<'info, State>,
-    #[account(mut)]
-    pub escrow_token_account: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub node_token_account: Account<'info, TokenAccount>,
-    pub token_program: Program<'info, Token>,
+#[event]
+pub struct NodeMarkedInactive {
+    pub node: Pubkey,
+    pub last_heartbeat_ts: i64,
 }
 
-#[derive(Accounts)]
-pub struct SlashNode<'info> {
-    pub authority: Signer<'info>,
-    #[account(seeds = [b"state", state.authority.as_ref()], bump = state.bump)]
-    pub state: Account<'info, State>,
-    #[account(mut)]
-    pub node: Account<'info, Node>,
+#[event]
+pub struct NodeReactivated {
+    pub node: Pubkey,
 }
 
-#[account]
-pub struct State {
-    pub authority: Pubkey,
-    pub attestor: Pubkey,
-    pub mint: Pubkey,
-    pub reward_rate_bps: u16,
-    pub bump: u8,
+#[event]
+pub struct NodeDeactivated {
+    pub node: Pubkey,
 }
 
-impl State {
-    pub const SIZE: usize = 32 + 32 + 32 + 2 + 1;
+#[event]
+pub struct UnstakeRequested {
+    pub node: Pubkey,
+    pub stake_lamports: u64,
+    pub withdrawable_at: i64,
 }
 
-#[account]
-pub struct Node {
+#[event]
+pub struct NodeClosed {
+    pub node: Pubkey,
     pub operator: Pubkey,
-    pub bandwidth_mbps: u32,
-    pub meta_hash: [u8; 32],
-    pub stake_lamports: u64,
-    pub total_bytes_relayed: u64,
-    pub unclaimed_reward: u64,
-    pub wg_pubkey: [u8; 32],
-    pub rating_sum: u64,
-    pub rating_count: u32,
-    pub active: bool,
-    pub registered_at: i64,
-    pub last_slash_ts: i64,
+    pub returned_lamports: u64,
 }
 
-impl Node {
-    pub const SIZE: usize = 32 + 4 + 32 + 8 + 8 + 8 + 32 + 8 + 4 + 1 + 8 + 8;
+#[event]
+pub struct NodeSlashed {
+    pub node: Pubkey,
+    pub amount: u64,
+    pub reason: SlashReason,
+    pub evidence_hash: [u8; 32],
+    pub remaining_stake: u64,
+    pub deactivated: bool,
+    /// Part of `amount` taken from delegated stake
+    pub delegator_amount: u64,
+    /// Token stake slashed; `amount` includes its lamport credit
+    pub tokens: u64,
 }
 
-#[account]
-pub struct Session {
-    pub user: Pubkey,
+#[event]
+pub struct TokensStaked {
     pub node: Pubkey,
-    pub deposit_amount: u64,
-    pub bytes_used: u64,
-    pub started_at: i64,
-    pub closed: bool,
-    pub bump: u8,
+    pub amount: u64,
+    pub staked_tokens: u64,
 }
 
-impl Session {
-    pub const SIZE: usize = 32 + 32 + 8 + 8 + 8 + 1 + 1;
+#[event]
+pub struct TokenUnstakeRequested {
+    pub node: Pubkey,
+    pub amount: u64,
+    pub withdrawable_at: i64,
 }
 
 #[event]
-pub struct SessionStarted {
-    pub session: Pubkey,
-    pub user: Pubkey,
+pub struct TokensWithdrawn {
     pub node: Pubkey,
-    pub deposit: u64,
+    pub amount: u64,
+    pub staked_tokens: u64,
 }
 
 #[event]
-pub struct UsageSubmitted {
-    pub session: Pubkey,
-    pub bytes: u64,
+pub struct SlashedTokensSwept {
+    pub node: Pubkey,
+    pub amount: u64,
 }
 
 #[event]
-pub struct SessionSettled {
-    pub session: Pubkey,
-    pub payout: u64,
-    pub bytes: u64,
+pub struct PaymentMintAdded {
+    pub mint: Pubkey,
 }
 
 #[event]
-pub struct NodeSlashed {
+pub struct PaymentMintRemoved {
+    pub mint: Pubkey,
+}
+
+#[event]
+pub struct PaymentRateUpdated {
+    pub node: Pubkey,
+    pub mint: Pubkey,
+    pub rate: u64,
+}
+
+#[event]
+pub struct CommissionUpdated {
+    pub node: Pubkey,
+    pub commission_bps: u16,
+}
+
+#[event]
+pub struct StakeDelegated {
+    pub node: Pubkey,
+    pub delegator: Pubkey,
+    pub lamports: u64,
+    pub shares: u64,
+}
+
+#[event]
+pub struct UndelegationRequested {
+    pub node: Pubkey,
+    pub delegator: Pubkey,
+    pub shares: u64,
+    pub withdrawable_at: i64,
+}
+
+#[event]
+pub struct DelegationWithdrawn {
+    pub node: Pubkey,
+    pub delegator: Pubkey,
+    pub shares: u64,
+    pub lamports: u64,
+}
+
+#[event]
+pub struct DelegatorRewardsClaimed {
     pub node: Pubkey,
+    pub delegator: Pubkey,
     pub amount: u64,
 }
 
@@ -558,6 +4564,341 @@ pub enum DvpnError {
     MathOverflow,
     #[msg("Session already closed")]
     SessionClosed,
+    #[msg("Deposit must be greater than zero")]
+    InvalidDeposit,
+    #[msg("Missing Ed25519 signature instruction")]
+    MissingEd25519Instruction,
+    #[msg("Attestation does not match the expected signer or message")]
+    InvalidAttestation,
+    #[msg("Attestation expired")]
+    AttestationExpired,
+    #[msg("Attestation nonce already used")]
+    AttestationReplayed,
+    #[msg("Invalid ZK proof")]
+    InvalidProof,
+    #[msg("Node is not active")]
+    NodeInactive,
+    #[msg("Node is already unbonding")]
+    AlreadyUnbonding,
+    #[msg("Node has not requested unstake")]
+    NotUnbonding,
+    #[msg("Unbonding period has not elapsed")]
+    UnbondingNotElapsed,
+    #[msg("Node still has open sessions")]
+    OpenSessions,
+    #[msg("Node has unclaimed rewards")]
+    UnclaimedRewards,
+    #[msg("Stake below the configured minimum")]
+    InsufficientStake,
+    #[msg("Pricing was updated too recently")]
+    PricingCooldown,
+    #[msg("Fee must not exceed 10000 bps")]
+    InvalidFee,
+    #[msg("Treasury token account does not match state")]
+    InvalidTreasury,
+    #[msg("Session has not been settled")]
+    SessionOpen,
+    #[msg("Session duration out of range")]
+    InvalidDuration,
+    #[msg("Session expired")]
+    SessionExpired,
+    #[msg("Session has not expired yet")]
+    SessionNotExpired,
+    #[msg("Usage was already submitted for this session")]
+    UsageSubmitted,
+    #[msg("Not enough attestor approvals")]
+    InsufficientApprovals,
+    #[msg("Attestor already registered")]
+    AttestorExists,
+    #[msg("Attestor not registered")]
+    AttestorNotFound,
+    #[msg("Attestor registry is full")]
+    TooManyAttestors,
+    #[msg("Threshold must be between 1 and the number of attestors")]
+    InvalidThreshold,
+    #[msg("No authority transfer pending")]
+    NoPendingAuthority,
+    #[msg("Account is not a dVPN state account")]
+    InvalidStateAccount,
+    #[msg("Program or node is paused")]
+    Paused,
+    #[msg("Proposal is not queued")]
+    ProposalNotQueued,
+    #[msg("Proposal timelock has not elapsed")]
+    ProposalNotReady,
+    #[msg("Proposal grace period has passed")]
+    ProposalExpired,
+    #[msg("Timelock delay out of range")]
+    InvalidDelay,
+    #[msg("No epoch is active at this time")]
+    EpochNotActive,
+    #[msg("Epoch has not ended yet")]
+    EpochNotFinalized,
+    #[msg("Invalid epoch account or range")]
+    InvalidEpoch,
+    #[msg("Epoch reward already claimed")]
+    RewardAlreadyClaimed,
+    #[msg("Maximum token supply exceeded")]
+    SupplyCapExceeded,
+    #[msg("Invalid reward multiplier parameters")]
+    InvalidRewardParams,
+    #[msg("Rating must be between 1 and 5")]
+    InvalidRating,
+    #[msg("Session has not been settled")]
+    SessionNotSettled,
+    #[msg("Session already rated")]
+    AlreadyRated,
+    #[msg("Session is not pending settlement")]
+    SessionNotPending,
+    #[msg("Dispute window is still open")]
+    DisputeWindowOpen,
+    #[msg("Dispute window has closed")]
+    DisputeWindowClosed,
+    #[msg("Disputed byte count must be below the submitted usage")]
+    InvalidDispute,
+    #[msg("Invalid node metadata")]
+    InvalidMetadata,
+    #[msg("Node has reached its session limit")]
+    NodeAtCapacity,
+    #[msg("Node has not sent a heartbeat recently")]
+    NodeStale,
+    #[msg("Node heartbeat is still within the staleness window")]
+    NodeNotStale,
+    #[msg("Commission must be at most 10000 bps")]
+    InvalidCommission,
+    #[msg("Invalid delegation amount")]
+    InvalidDelegation,
+    #[msg("Node still has delegated stake")]
+    DelegationsOutstanding,
+    #[msg("Node still has token stake")]
+    TokenStakeOutstanding,
+    #[msg("Mint is not an accepted payment mint")]
+    PaymentMintNotAccepted,
+    #[msg("Payment mint already accepted")]
+    PaymentMintExists,
+    #[msg("Payment mint not found")]
+    PaymentMintNotFound,
+    #[msg("Payment mint list is full")]
+    TooManyPaymentMints,
+    #[msg("Node has no price for this payment mint")]
+    NoPaymentRate,
+    #[msg("The reward mint is always priced at par")]
+    InvalidPaymentRate,
+    #[msg("Usage receipt has expired")]
+    ReceiptExpired,
+    #[msg("Token stake vault accounts missing or mismatched")]
+    InvalidVault,
+    #[msg("Fee treasury still holds tokens")]
+    TreasuryNotEmpty,
+    #[msg("Account is not a dVPN node account")]
+    InvalidNodeAccount,
+    #[msg("Proposal has not been executed")]
+    ProposalNotExecuted,
+    #[msg("Verifying key was not approved by this proposal")]
+    VerifyingKeyNotApproved,
+    #[msg("Session is not disputed")]
+    SessionNotDisputed,
+    #[msg("Dispute can still be resolved")]
+    DisputeResolutionOpen,
+    #[msg("Disputes are disabled until a bond and slash amount are configured")]
+    DisputesNotConfigured,
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pricing(price_per_mib: u64, price_per_minute: u64, min_charge: u64) -> PriceSchedule {
+        PriceSchedule {
+            price_per_mib,
+            price_per_minute,
+            min_charge,
+        }
+    }
+
+    /// An account of `len` zero bytes after the discriminator, decoded
+    fn zeroed<T: AccountDeserialize + Discriminator>(len: usize) -> T {
+        let mut data = vec![0u8; 8 + len];
+        data[..8].copy_from_slice(&T::DISCRIMINATOR);
+        T::try_deserialize(&mut data.as_slice()).unwrap()
+    }
+
+    fn node_with_stake(stake_lamports: u64, commission_bps: u16) -> Node {
+        let mut node: Node = zeroed(Node::SIZE);
+        node.stake_lamports = stake_lamports;
+        node.commission_bps = commission_bps;
+        node
+    }
+
+    /// `delegate_stake` bookkeeping, without the lamport transfer
+    fn delegate(node: &mut Node, delegation: &mut Delegation, lamports: u64) -> Result<u64> {
+        let shares = node.shares_for(lamports)?;
+        delegation.accrue(node)?;
+        delegation.shares += shares;
+        delegation.sync_reward_debt(node);
+        node.delegated_lamports += lamports;
+        node.delegation_shares += shares;
+        Ok(shares)
+    }
+
+    /// `withdraw_delegation` bookkeeping for all of a delegation's shares; returns the lamports paid
+    fn withdraw_all(node: &mut Node, delegation: &mut Delegation) -> u64 {
+        let shares = delegation.shares;
+        let lamports = node.shares_value(shares);
+        delegation.accrue(node).unwrap();
+        delegation.shares = 0;
+        node.delegated_lamports = node.delegated_lamports.checked_sub(lamports).unwrap();
+        node.delegation_shares = node.delegation_shares.checked_sub(shares).unwrap();
+        delegation.sync_reward_debt(node);
+        lamports
+    }
+
+    #[test]
+    fn compute_payout_zero_bytes() {
+        assert_eq!(compute_payout(&pricing(1_000, 0, 0), 0, 0, 10_000).unwrap(), 0);
+        // Time and the minimum charge still apply with no traffic
+        assert_eq!(compute_payout(&pricing(1_000, 10, 0), 0, 61, 10_000).unwrap(), 20);
+        assert_eq!(compute_payout(&pricing(1_000, 0, 50), 0, 0, 10_000).unwrap(), 50);
+    }
+
+    #[test]
+    fn compute_payout_does_not_overflow_u64() {
+        // Products past u64::MAX are computed in u128 and then capped at the deposit
+        let max = pricing(u64::MAX, u64::MAX, u64::MAX);
+        assert_eq!(compute_payout(&max, u64::MAX, i64::MAX, u64::MAX).unwrap(), u64::MAX);
+        assert_eq!(compute_payout(&max, u64::MAX, i64::MAX, 7).unwrap(), 7);
+        assert_eq!(
+            compute_payout(&pricing(u64::MAX, 0, 0), 2 * BYTES_PER_MIB as u64, 0, u64::MAX).unwrap(),
+            u64::MAX
+        );
+    }
+
+    #[test]
+    fn compute_payout_capped_at_deposit() {
+        let p = pricing(100, 0, 0);
+        let mib = BYTES_PER_MIB as u64;
+        assert_eq!(compute_payout(&p, 5 * mib, 0, 1_000).unwrap(), 500);
+        assert_eq!(compute_payout(&p, 50 * mib, 0, 1_000).unwrap(), 1_000);
+        // A minimum charge above the deposit is capped too
+        assert_eq!(compute_payout(&pricing(0, 0, 5_000), 0, 0, 1_000).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn first_delegation_mints_shares_one_to_one() {
+        let mut node = node_with_stake(1_000_000_000, 0);
+        let mut delegation: Delegation = zeroed(Delegation::SIZE);
+        assert_eq!(delegate(&mut node, &mut delegation, 500_000_000).unwrap(), 500_000_000);
+        assert_eq!(node.delegated_lamports, 500_000_000);
+        assert_eq!(node.shares_value(delegation.shares), 500_000_000);
+    }
+
+    #[test]
+    fn delegation_after_a_reward_earns_only_later_rewards() {
+        let mut node = node_with_stake(1_000, 0);
+        let mut early: Delegation = zeroed(Delegation::SIZE);
+        let mut late: Delegation = zeroed(Delegation::SIZE);
+        delegate(&mut node, &mut early, 1_000).unwrap();
+
+        // Half the stake is delegated, so delegators get half the reward
+        assert_eq!(node.distribute_reward(1_000, 0).unwrap(), (500, 500));
+        // Rewards are minted separately, so the share price is unchanged
+        assert_eq!(delegate(&mut node, &mut late, 1_000).unwrap(), 1_000);
+        late.accrue(&node).unwrap();
+        assert_eq!(late.pending_reward, 0);
+
+        assert_eq!(node.distribute_reward(3_000, 0).unwrap(), (1_000, 2_000));
+        early.accrue(&node).unwrap();
+        late.accrue(&node).unwrap();
+        assert_eq!(early.pending_reward, 500 + 1_000);
+        assert_eq!(late.pending_reward, 1_000);
+    }
+
+    #[test]
+    fn delegator_commission_bounds() {
+        let mut delegation: Delegation = zeroed(Delegation::SIZE);
+        let mut no_commission = node_with_stake(1_000, 0);
+        delegate(&mut no_commission, &mut delegation, 3_000).unwrap();
+        assert_eq!(no_commission.distribute_reward(4_000, 0).unwrap(), (1_000, 3_000));
+
+        // The operator keeps the delegators' whole share at 10_000 bps
+        let mut delegation: Delegation = zeroed(Delegation::SIZE);
+        let mut full_commission = node_with_stake(1_000, MAX_BPS);
+        delegate(&mut full_commission, &mut delegation, 3_000).unwrap();
+        assert_eq!(full_commission.distribute_reward(4_000, 0).unwrap(), (4_000, 0));
+        assert_eq!(full_commission.delegator_reward_per_share, 0);
+        delegation.accrue(&full_commission).unwrap();
+        assert_eq!(delegation.pending_reward, 0);
+    }
+
+    #[test]
+    fn delegation_after_a_slash_wipes_the_pool() {
+        let mut node = node_with_stake(1_000, 0);
+        let mut old: Delegation = zeroed(Delegation::SIZE);
+        delegate(&mut node, &mut old, 1_000).unwrap();
+        // What `slash_stake` leaves once the delegated lamports are all slashed
+        node.delegated_lamports = 0;
+
+        // Nobody buys into worthless shares while any are outstanding
+        let mut new: Delegation = zeroed(Delegation::SIZE);
+        assert_eq!(delegate(&mut node, &mut new, 500).unwrap_err(), DvpnError::InvalidDelegation.into());
+
+        // Once the old shares are withdrawn (for nothing) the pool restarts at par
+        assert_eq!(withdraw_all(&mut node, &mut old), 0);
+        assert_eq!(node.delegation_shares, 0);
+        assert_eq!(delegate(&mut node, &mut new, 500).unwrap(), 500);
+        assert_eq!(node.shares_value(new.shares), 500);
+    }
 
+    #[test]
+    fn delegation_rounding_never_pays_out_more_than_the_pool() {
+        let mut node = node_with_stake(1_000_000_000, 0);
+        let mut delegations: Vec<Delegation> = (0..4).map(|_| zeroed(Delegation::SIZE)).collect();
+        let mut deposited = 0;
+        for (delegation, lamports) in delegations.iter_mut().zip([333, 1_000_001, 7]) {
+            delegate(&mut node, delegation, lamports).unwrap();
+            deposited += lamports;
+        }
+        // An odd slash leaves a share price that doesn't divide evenly, then someone joins at it
+        node.delegated_lamports -= 101;
+        deposited -= 101;
+        delegate(&mut node, &mut delegations[3], 999).unwrap();
+        deposited += 999;
+
+        let mut paid = 0;
+        for delegation in delegations.iter_mut() {
+            paid += withdraw_all(&mut node, delegation);
+        }
+        // Rounding dust stays in the node instead of being paid twice
+        assert!(paid <= deposited);
+        assert_eq!(paid + node.delegated_lamports, deposited);
+        assert_eq!(node.delegation_shares, 0);
+    }
+
+    #[test]
+    fn node_layout_matches_client_offsets() {
+        // apps/web/lib/solana.ts reads these offsets
+        let mut node: Node = zeroed(Node::SIZE);
+        node.last_heartbeat_ts = 0x0101_0101;
+        node.commission_bps = 0x0202;
+        node.delegated_lamports = 0x0303;
+        node.staked_tokens = 0x0404;
+        node.unclaimed_epochs = 0x0505;
+        node.metadata.endpoint_port = 0x0606;
+        node.metadata.endpoint_host = "a.example".to_string();
+
+        let mut data = Vec::new();
+        node.try_serialize(&mut data).unwrap();
+        assert_eq!(Node::LEGACY_METADATA_OFFSET, 634);
+        assert_eq!(Node::METADATA_OFFSET, 881);
+        assert_eq!(data[634..642], 0x0101_0101i64.to_le_bytes());
+        assert_eq!(data[651..653], 0x0202u16.to_le_bytes());
+        assert_eq!(data[653..661], 0x0303u64.to_le_bytes());
+        assert_eq!(data[685..693], 0x0404u64.to_le_bytes());
+        assert_eq!(data[877..881], 0x0505u32.to_le_bytes());
+        assert_eq!(data[881 + 2..881 + 4], 0x0606u16.to_le_bytes());
+        assert_eq!(data[881 + 10..881 + 14], 9u32.to_le_bytes());
+        assert_eq!(&data[881 + 14..881 + 23], b"a.example");
+        // A short host leaves the rest of the account unused rather than shifting any field
+        assert!(data.len() < 8 + Node::SIZE);
+    }
+}
@@ -0,0 +1,102 @@
+//! settle_session_with_attestation against forged, replayed and mismatched Ed25519 attestations
+
+mod common;
+
+use common::{assert_dvpn_error, ed25519_ix, ed25519_ix_with_index, TestEnv};
+use dvpn::{attestation_message, DvpnError, SessionStatus};
+use solana_sdk::signature::{Keypair, Signer};
+
+const TOTAL_BYTES: u64 = 10 * 1_048_576;
+
+#[tokio::test]
+async fn settles_with_registered_attestor_signature() {
+    let mut env = TestEnv::start().await;
+    let expires_at = env.now().await + 60;
+    let message = attestation_message(&env.session, &env.user.pubkey(), &env.node, TOTAL_BYTES, 1, expires_at);
+    let ixs = [
+        ed25519_ix(&env.attestor, &message),
+        env.settle_with_attestation_ix(TOTAL_BYTES, 1, expires_at),
+    ];
+    env.send(&ixs, &[]).await.unwrap();
+
+    let session = env.session().await;
+    assert_eq!(session.status, SessionStatus::Settled);
+    assert_eq!(session.attestation_nonce, 1);
+}
+
+#[tokio::test]
+async fn rejects_attestation_from_unregistered_signer() {
+    let mut env = TestEnv::start().await;
+    let expires_at = env.now().await + 60;
+    let message = attestation_message(&env.session, &env.user.pubkey(), &env.node, TOTAL_BYTES, 1, expires_at);
+    let forger = Keypair::new();
+    let ixs = [
+        ed25519_ix(&forger, &message),
+        env.settle_with_attestation_ix(TOTAL_BYTES, 1, expires_at),
+    ];
+    assert_dvpn_error(env.send(&ixs, &[]).await, DvpnError::InsufficientApprovals);
+}
+
+#[tokio::test]
+async fn rejects_attestation_with_offsets_into_another_instruction() {
+    let mut env = TestEnv::start().await;
+    let expires_at = env.now().await + 60;
+    let message = attestation_message(&env.session, &env.user.pubkey(), &env.node, TOTAL_BYTES, 1, expires_at);
+    // Index 0 is this same instruction, but only u16::MAX is accepted as "inline"
+    let ixs = [
+        ed25519_ix_with_index(&env.attestor, &message, 0),
+        env.settle_with_attestation_ix(TOTAL_BYTES, 1, expires_at),
+    ];
+    assert_dvpn_error(env.send(&ixs, &[]).await, DvpnError::InsufficientApprovals);
+}
+
+#[tokio::test]
+async fn rejects_replayed_attestation() {
+    let mut env = TestEnv::start().await;
+    let expires_at = env.now().await + 60;
+    let message = attestation_message(&env.session, &env.user.pubkey(), &env.node, TOTAL_BYTES, 1, expires_at);
+    let ixs = [
+        ed25519_ix(&env.attestor, &message),
+        env.settle_with_attestation_ix(TOTAL_BYTES, 1, expires_at),
+    ];
+    env.send(&ixs, &[]).await.unwrap();
+    assert_dvpn_error(env.send(&ixs, &[]).await, DvpnError::SessionClosed);
+}
+
+#[tokio::test]
+async fn rejects_stale_attestation_nonce() {
+    let mut env = TestEnv::start().await;
+    let expires_at = env.now().await + 60;
+    // Nonces must be above the session's last accepted one, which starts at 0
+    let message = attestation_message(&env.session, &env.user.pubkey(), &env.node, TOTAL_BYTES, 0, expires_at);
+    let ixs = [
+        ed25519_ix(&env.attestor, &message),
+        env.settle_with_attestation_ix(TOTAL_BYTES, 0, expires_at),
+    ];
+    assert_dvpn_error(env.send(&ixs, &[]).await, DvpnError::AttestationReplayed);
+}
+
+#[tokio::test]
+async fn rejects_attestation_over_a_different_message() {
+    let mut env = TestEnv::start().await;
+    let expires_at = env.now().await + 60;
+    // The attestor signed a lower byte count than the settlement claims
+    let message = attestation_message(&env.session, &env.user.pubkey(), &env.node, TOTAL_BYTES / 2, 1, expires_at);
+    let ixs = [
+        ed25519_ix(&env.attestor, &message),
+        env.settle_with_attestation_ix(TOTAL_BYTES, 1, expires_at),
+    ];
+    assert_dvpn_error(env.send(&ixs, &[]).await, DvpnError::InsufficientApprovals);
+}
+
+#[tokio::test]
+async fn rejects_expired_attestation() {
+    let mut env = TestEnv::start().await;
+    let expires_at = env.now().await - 1;
+    let message = attestation_message(&env.session, &env.user.pubkey(), &env.node, TOTAL_BYTES, 1, expires_at);
+    let ixs = [
+        ed25519_ix(&env.attestor, &message),
+        env.settle_with_attestation_ix(TOTAL_BYTES, 1, expires_at),
+    ];
+    assert_dvpn_error(env.send(&ixs, &[]).await, DvpnError::AttestationExpired);
+}
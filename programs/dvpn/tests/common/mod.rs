@@ -0,0 +1,410 @@
+//! Shared program-test setup: a state with an attestor registry, one registered node and an
+//! active session paid in an SPL token.
+
+#![allow(dead_code)]
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::solana_program::program_option::COption;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::solana_program::{ed25519_program, instruction::Instruction, system_program, sysvar};
+use anchor_lang::{InstructionData, ToAccountMetas};
+use anchor_spl::associated_token::get_associated_token_address_with_program_id;
+use anchor_spl::token::spl_token;
+use dvpn::DvpnError;
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::account::Account;
+use solana_sdk::instruction::InstructionError;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::{Transaction, TransactionError};
+
+pub const DEPOSIT: u64 = 1_000_000;
+/// `DEFAULT_DISPUTE_BOND_TOKENS` at the test mint's 6 decimals; the user holds it on top of the deposit
+pub const DISPUTE_BOND: u64 = 10_000_000;
+pub const SESSION_DURATION_SECS: i64 = 3_600;
+
+// Anchor's entrypoint wants the account slice and its infos to share one lifetime
+fn process_instruction(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let accounts = Box::leak(Box::new(accounts.to_vec()));
+    dvpn::entry(program_id, accounts, data)
+}
+
+pub struct TestEnv {
+    pub ctx: ProgramTestContext,
+    /// State authority, and the only attestor in the registry
+    pub attestor: Keypair,
+    pub operator: Keypair,
+    pub user: Keypair,
+    pub mint: Pubkey,
+    pub state: Pubkey,
+    pub registry: Pubkey,
+    pub treasury: Pubkey,
+    pub node: Pubkey,
+    pub node_token_account: Pubkey,
+    pub user_token_account: Pubkey,
+    pub session: Pubkey,
+}
+
+fn token_account(mint: &Pubkey, owner: &Pubkey, amount: u64) -> Account {
+    let mut data = vec![0; spl_token::state::Account::LEN];
+    spl_token::state::Account {
+        mint: *mint,
+        owner: *owner,
+        amount,
+        state: spl_token::state::AccountState::Initialized,
+        ..Default::default()
+    }
+    .pack_into_slice(&mut data);
+    Account {
+        lamports: 1_000_000_000,
+        data,
+        owner: spl_token::ID,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+fn system_account(lamports: u64) -> Account {
+    Account::new(lamports, 0, &system_program::ID)
+}
+
+impl TestEnv {
+    /// Set up the program state, attestor registry and node, and start one session
+    pub async fn start() -> Self {
+        let mut program_test = ProgramTest::new("dvpn", dvpn::ID, processor!(process_instruction));
+        program_test.prefer_bpf(false);
+
+        let attestor = Keypair::new();
+        let operator = Keypair::new();
+        let user = Keypair::new();
+        for key in [&attestor, &operator, &user] {
+            program_test.add_account(key.pubkey(), system_account(100_000_000_000));
+        }
+
+        let mint = Pubkey::new_unique();
+        let mut mint_data = vec![0; spl_token::state::Mint::LEN];
+        spl_token::state::Mint {
+            mint_authority: COption::Some(attestor.pubkey()),
+            supply: DEPOSIT + DISPUTE_BOND,
+            decimals: 6,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        }
+        .pack_into_slice(&mut mint_data);
+        program_test.add_account(
+            mint,
+            Account {
+                lamports: 1_000_000_000,
+                data: mint_data,
+                owner: spl_token::ID,
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+        let node_token_account = Pubkey::new_unique();
+        program_test.add_account(node_token_account, token_account(&mint, &operator.pubkey(), 0));
+        let user_token_account = Pubkey::new_unique();
+        program_test.add_account(user_token_account, token_account(&mint, &user.pubkey(), DEPOSIT + DISPUTE_BOND));
+
+        let ctx = program_test.start_with_context().await;
+
+        let (state, state_bump) = Pubkey::find_program_address(&[b"state", attestor.pubkey().as_ref()], &dvpn::ID);
+        let (treasury, _) = Pubkey::find_program_address(&[b"treasury", state.as_ref()], &dvpn::ID);
+        let (registry, _) = Pubkey::find_program_address(&[b"attestors", state.as_ref()], &dvpn::ID);
+        let (node, _) = Pubkey::find_program_address(&[b"node", operator.pubkey().as_ref()], &dvpn::ID);
+        let (session_counter, _) =
+            Pubkey::find_program_address(&[b"session_counter", user.pubkey().as_ref()], &dvpn::ID);
+        let (session, _) = Pubkey::find_program_address(
+            &[b"session", user.pubkey().as_ref(), node.as_ref(), &0u64.to_le_bytes()],
+            &dvpn::ID,
+        );
+
+        let mut env = TestEnv {
+            ctx,
+            attestor,
+            operator,
+            user,
+            mint,
+            state,
+            registry,
+            treasury,
+            node,
+            node_token_account,
+            user_token_account,
+            session,
+        };
+
+        let initialize_state = Instruction {
+            program_id: dvpn::ID,
+            accounts: dvpn::accounts::InitializeState {
+                authority: env.attestor.pubkey(),
+                state,
+                state_signer: state,
+                dvpn_mint: mint,
+                treasury,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: dvpn::instruction::InitializeState {
+                bump: state_bump,
+                reward_rate_bps: 0,
+            }
+            .data(),
+        };
+        let initialize_registry = Instruction {
+            program_id: dvpn::ID,
+            accounts: dvpn::accounts::InitializeAttestorRegistry {
+                authority: env.attestor.pubkey(),
+                state,
+                attestor_registry: registry,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: dvpn::instruction::InitializeAttestorRegistry {}.data(),
+        };
+        env.send(&[initialize_state, initialize_registry], &[&env.attestor.insecure_clone()])
+            .await
+            .unwrap();
+
+        let register_node = Instruction {
+            program_id: dvpn::ID,
+            accounts: dvpn::accounts::RegisterNode {
+                operator: env.operator.pubkey(),
+                state,
+                node,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: dvpn::instruction::RegisterNode {
+                stake_lamports: 1_000_000_000,
+                bandwidth_mbps: 100,
+                meta_hash: [0; 32],
+                wg_pubkey: None,
+            }
+            .data(),
+        };
+        env.send(&[register_node], &[&env.operator.insecure_clone()]).await.unwrap();
+
+        let start_session = Instruction {
+            program_id: dvpn::ID,
+            accounts: dvpn::accounts::StartSession {
+                session_counter,
+                session,
+                user: env.user.pubkey(),
+                node,
+                state,
+                payment_mint: mint,
+                user_token_account,
+                escrow_token_account: env.escrow(),
+                token_program: spl_token::ID,
+                associated_token_program: anchor_spl::associated_token::ID,
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: dvpn::instruction::StartSession {
+                deposit_amount: DEPOSIT,
+                max_duration: SESSION_DURATION_SECS,
+            }
+            .data(),
+        };
+        env.send(&[start_session], &[&env.user.insecure_clone()]).await.unwrap();
+        env
+    }
+
+    pub fn escrow(&self) -> Pubkey {
+        get_associated_token_address_with_program_id(&self.session, &self.mint, &spl_token::ID)
+    }
+
+    /// Sign and send `instructions` with a fresh blockhash, so identical retries aren't deduplicated
+    pub async fn send(&mut self, instructions: &[Instruction], signers: &[&Keypair]) -> std::result::Result<(), BanksClientError> {
+        let blockhash = self.ctx.get_new_latest_blockhash().await.unwrap();
+        let payer = self.ctx.payer.insecure_clone();
+        let mut all_signers = vec![&payer];
+        all_signers.extend_from_slice(signers);
+        let tx = Transaction::new_signed_with_payer(instructions, Some(&payer.pubkey()), &all_signers, blockhash);
+        self.ctx.banks_client.process_transaction(tx).await
+    }
+
+    pub async fn session(&mut self) -> dvpn::Session {
+        self.account(self.session).await
+    }
+
+    pub async fn account<T: AccountDeserialize>(&mut self, address: Pubkey) -> T {
+        let account = self.ctx.banks_client.get_account(address).await.unwrap().unwrap();
+        T::try_deserialize(&mut account.data.as_slice()).unwrap()
+    }
+
+    pub fn settle_with_attestation_ix(&self, total_bytes: u64, nonce: u64, expires_at: i64) -> Instruction {
+        Instruction {
+            program_id: dvpn::ID,
+            accounts: dvpn::accounts::SettleWithAttestation {
+                session: self.session,
+                node: self.node,
+                user: self.user.pubkey(),
+                state: self.state,
+                attestor_registry: self.registry,
+                payment_mint: self.mint,
+                escrow_token_account: self.escrow(),
+                node_token_account: self.node_token_account,
+                user_token_account: self.user_token_account,
+                treasury: self.treasury,
+                treasury_token_account: None,
+                token_program: spl_token::ID,
+                instructions: sysvar::instructions::ID,
+            }
+            .to_account_metas(None),
+            data: dvpn::instruction::SettleSessionWithAttestation {
+                total_bytes,
+                nonce,
+                expires_at,
+            }
+            .data(),
+        }
+    }
+
+    pub fn force_settle_expired_ix(&self, authority: &Pubkey) -> Instruction {
+        Instruction {
+            program_id: dvpn::ID,
+            accounts: dvpn::accounts::ForceSettleExpired {
+                session: self.session,
+                node: self.node,
+                state: self.state,
+                authority: *authority,
+            }
+            .to_account_metas(None),
+            data: dvpn::instruction::ForceSettleExpired {}.data(),
+        }
+    }
+
+    pub fn submit_usage_ix(&self, bytes: u64) -> Instruction {
+        Instruction {
+            program_id: dvpn::ID,
+            accounts: dvpn::accounts::SubmitUsage {
+                attestor: self.attestor.pubkey(),
+                state: self.state,
+                attestor_registry: self.registry,
+                session: self.session,
+                user: self.user.pubkey(),
+            }
+            .to_account_metas(None),
+            data: dvpn::instruction::SubmitUsage { bytes }.data(),
+        }
+    }
+
+    pub fn settle_session_ix(&self) -> Instruction {
+        Instruction {
+            program_id: dvpn::ID,
+            accounts: dvpn::accounts::RequestSettlement {
+                session: self.session,
+                node: self.node,
+                state: self.state,
+                attestor_registry: self.registry,
+                authority: self.user.pubkey(),
+            }
+            .to_account_metas(None),
+            data: dvpn::instruction::SettleSession {}.data(),
+        }
+    }
+
+    pub fn dispute_session_ix(&self, user_bytes: u64) -> Instruction {
+        Instruction {
+            program_id: dvpn::ID,
+            accounts: dvpn::accounts::DisputeSession {
+                user: self.user.pubkey(),
+                session: self.session,
+                state: self.state,
+                payment_mint: self.mint,
+                escrow_token_account: self.escrow(),
+                user_token_account: self.user_token_account,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: dvpn::instruction::DisputeSession { user_bytes }.data(),
+        }
+    }
+
+    pub fn expire_dispute_ix(&self) -> Instruction {
+        Instruction {
+            program_id: dvpn::ID,
+            accounts: dvpn::accounts::SettleSession {
+                session: self.session,
+                node: self.node,
+                user: self.user.pubkey(),
+                state: self.state,
+                payment_mint: self.mint,
+                escrow_token_account: self.escrow(),
+                node_token_account: self.node_token_account,
+                user_token_account: self.user_token_account,
+                treasury: self.treasury,
+                treasury_token_account: None,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: dvpn::instruction::ExpireDispute {}.data(),
+        }
+    }
+
+    pub async fn token_balance(&mut self, address: Pubkey) -> u64 {
+        let account = self.ctx.banks_client.get_account(address).await.unwrap().unwrap();
+        spl_token::state::Account::unpack(&account.data).unwrap().amount
+    }
+
+    pub async fn now(&mut self) -> i64 {
+        self.ctx.banks_client.get_sysvar::<Clock>().await.unwrap().unix_timestamp
+    }
+
+    /// Warp to a later slot and move the cluster clock forward by `secs`
+    pub async fn advance_clock(&mut self, secs: i64) {
+        let slot = self.ctx.banks_client.get_root_slot().await.unwrap();
+        self.ctx.warp_to_slot(slot + 100).unwrap();
+        let mut clock = self.ctx.banks_client.get_sysvar::<Clock>().await.unwrap();
+        clock.unix_timestamp += secs;
+        self.ctx.set_sysvar(&clock);
+    }
+}
+
+/// Ed25519 program instruction in which `signer` signs `message`. `offsets_ix_index` is written
+/// into all three instruction-index fields; `u16::MAX` means "this instruction".
+pub fn ed25519_ix_with_index(signer: &Keypair, message: &[u8], offsets_ix_index: u16) -> Instruction {
+    const HEADER: usize = 2 + 14;
+    let pubkey_offset = HEADER;
+    let signature_offset = pubkey_offset + 32;
+    let message_offset = signature_offset + 64;
+
+    let mut data = Vec::with_capacity(message_offset + message.len());
+    data.extend_from_slice(&[1, 0]);
+    for field in [
+        signature_offset as u16,
+        offsets_ix_index,
+        pubkey_offset as u16,
+        offsets_ix_index,
+        message_offset as u16,
+        message.len() as u16,
+        offsets_ix_index,
+    ] {
+        data.extend_from_slice(&field.to_le_bytes());
+    }
+    data.extend_from_slice(signer.pubkey().as_ref());
+    data.extend_from_slice(signer.sign_message(message).as_ref());
+    data.extend_from_slice(message);
+    Instruction {
+        program_id: ed25519_program::ID,
+        accounts: vec![],
+        data,
+    }
+}
+
+pub fn ed25519_ix(signer: &Keypair, message: &[u8]) -> Instruction {
+    ed25519_ix_with_index(signer, message, u16::MAX)
+}
+
+/// Assert the transaction failed in a dvpn instruction with `expected`
+pub fn assert_dvpn_error(result: std::result::Result<(), BanksClientError>, expected: DvpnError) {
+    let expected_code: u32 = expected.into();
+    match result.expect_err("transaction should fail").unwrap() {
+        TransactionError::InstructionError(_, InstructionError::Custom(code)) => {
+            assert_eq!(code, expected_code, "expected {expected_code}, got {code}")
+        }
+        other => panic!("expected custom error {expected_code}, got {other:?}"),
+    }
+}
@@ -0,0 +1,84 @@
+//! Disputes nobody resolves: expire_dispute settles them on the node's usage once the
+//! resolution deadline passes, and disputes are refused while the penalties are unset
+
+mod common;
+
+use anchor_lang::{InstructionData, ToAccountMetas};
+use common::{assert_dvpn_error, TestEnv, DEPOSIT, DISPUTE_BOND};
+use dvpn::{DvpnError, SessionStatus, DISPUTE_RESOLUTION_SECS};
+use solana_sdk::instruction::Instruction;
+use solana_sdk::signature::Signer;
+
+const NODE_BYTES: u64 = 10 * 1_048_576;
+
+/// Submit usage, request settlement and dispute it as the user
+async fn disputed_env() -> TestEnv {
+    let mut env = TestEnv::start().await;
+    let attestor = env.attestor.insecure_clone();
+    let user = env.user.insecure_clone();
+    let ixs = [env.submit_usage_ix(NODE_BYTES), env.settle_session_ix()];
+    env.send(&ixs, &[&attestor, &user]).await.unwrap();
+    let ix = env.dispute_session_ix(NODE_BYTES / 2);
+    env.send(&[ix], &[&user]).await.unwrap();
+    env
+}
+
+#[tokio::test]
+async fn expire_dispute_waits_for_the_resolution_deadline() {
+    let mut env = disputed_env().await;
+    let session = env.session().await;
+    assert_eq!(session.status, SessionStatus::Disputed);
+    assert_eq!(session.dispute_bond, DISPUTE_BOND);
+    assert!(session.dispute_deadline >= env.now().await + DISPUTE_RESOLUTION_SECS);
+
+    let ix = env.expire_dispute_ix();
+    assert_dvpn_error(env.send(&[ix], &[]).await, DvpnError::DisputeResolutionOpen);
+}
+
+#[tokio::test]
+async fn expired_dispute_settles_on_node_usage_and_returns_the_bond() {
+    let mut env = disputed_env().await;
+    env.advance_clock(DISPUTE_RESOLUTION_SECS + 1).await;
+
+    // Permissionless: only the fee payer signs
+    let ix = env.expire_dispute_ix();
+    env.send(&[ix], &[]).await.unwrap();
+
+    let session = env.session().await;
+    assert_eq!(session.status, SessionStatus::Settled);
+    assert_eq!(session.bytes_used, NODE_BYTES);
+    let node: dvpn::Node = env.account(env.node).await;
+    assert_eq!(node.open_sessions, 0);
+    assert_eq!(node.slash_count, 0);
+
+    // Deposit and bond leave escrow in full, and the bond goes back to the user
+    let paid = env.token_balance(env.node_token_account).await;
+    assert_eq!(env.token_balance(env.user_token_account).await, DEPOSIT + DISPUTE_BOND - paid);
+    assert_eq!(env.token_balance(env.escrow()).await, 0);
+}
+
+#[tokio::test]
+async fn disputes_refused_until_penalties_are_configured() {
+    let mut env = TestEnv::start().await;
+    let attestor = env.attestor.insecure_clone();
+    let user = env.user.insecure_clone();
+    let unset = Instruction {
+        program_id: dvpn::ID,
+        accounts: dvpn::accounts::SetMinStake {
+            authority: attestor.pubkey(),
+            state: env.state,
+        }
+        .to_account_metas(None),
+        data: dvpn::instruction::SetDisputeConfig {
+            dispute_window_secs: dvpn::DEFAULT_DISPUTE_WINDOW_SECS,
+            dispute_bond: 0,
+            dispute_slash_lamports: 0,
+        }
+        .data(),
+    };
+    let ixs = [unset, env.submit_usage_ix(NODE_BYTES), env.settle_session_ix()];
+    env.send(&ixs, &[&attestor, &user]).await.unwrap();
+
+    let ix = env.dispute_session_ix(NODE_BYTES / 2);
+    assert_dvpn_error(env.send(&[ix], &[&user]).await, DvpnError::DisputesNotConfigured);
+}
@@ -0,0 +1,57 @@
+//! force_settle_expired on a warped clock: rejected before the session expires, accepted after,
+//! from any signer
+
+mod common;
+
+use common::{assert_dvpn_error, TestEnv, SESSION_DURATION_SECS};
+use dvpn::{DvpnError, SessionStatus};
+use solana_sdk::signature::{Keypair, Signer};
+
+#[tokio::test]
+async fn force_settle_rejected_before_expiry() {
+    let mut env = TestEnv::start().await;
+    let user = env.user.insecure_clone();
+    let ix = env.force_settle_expired_ix(&user.pubkey());
+
+    assert_dvpn_error(env.send(std::slice::from_ref(&ix), &[&user]).await, DvpnError::SessionNotExpired);
+
+    // Still rejected just before `expires_at`
+    env.advance_clock(SESSION_DURATION_SECS - 1).await;
+    let session = env.session().await;
+    assert!(env.now().await <= session.expires_at);
+    assert_dvpn_error(env.send(&[ix], &[&user]).await, DvpnError::SessionNotExpired);
+    assert_eq!(env.session().await.status, SessionStatus::Active);
+}
+
+#[tokio::test]
+async fn force_settle_after_expiry_opens_dispute_window() {
+    let mut env = TestEnv::start().await;
+    let operator = env.operator.insecure_clone();
+    let ix = env.force_settle_expired_ix(&operator.pubkey());
+
+    env.advance_clock(SESSION_DURATION_SECS + 1).await;
+    let now = env.now().await;
+    env.send(std::slice::from_ref(&ix), &[&operator]).await.unwrap();
+
+    let session = env.session().await;
+    assert_eq!(session.status, SessionStatus::Pending);
+    assert!(session.ended_at >= now);
+    assert!(session.dispute_deadline > session.ended_at);
+
+    // Only once
+    assert_dvpn_error(env.send(&[ix], &[&operator]).await, DvpnError::SessionClosed);
+}
+
+#[tokio::test]
+async fn force_settle_is_a_permissionless_crank() {
+    let mut env = TestEnv::start().await;
+    // Not the user, the operator or an attestor
+    let outsider = Keypair::new();
+    let ix = env.force_settle_expired_ix(&outsider.pubkey());
+
+    assert_dvpn_error(env.send(std::slice::from_ref(&ix), &[&outsider]).await, DvpnError::SessionNotExpired);
+
+    env.advance_clock(SESSION_DURATION_SECS + 1).await;
+    env.send(&[ix], &[&outsider]).await.unwrap();
+    assert_eq!(env.session().await.status, SessionStatus::Pending);
+}
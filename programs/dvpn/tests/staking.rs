@@ -0,0 +1,116 @@
+//! Nodes registered below the minimum stake start inactive and are activated by token stake
+
+mod common;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::{system_instruction, system_program};
+use anchor_lang::{InstructionData, ToAccountMetas};
+use anchor_spl::associated_token::get_associated_token_address;
+use anchor_spl::associated_token::spl_associated_token_account::instruction::create_associated_token_account;
+use anchor_spl::token::spl_token;
+use common::{assert_dvpn_error, TestEnv};
+use dvpn::DvpnError;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::signature::{Keypair, Signer};
+
+const MIN_STAKE: u64 = 2_000_000_000;
+/// One lamport of stake credit per token base unit
+const TOKEN_STAKE_RATE: u64 = 1_000_000_000;
+
+fn admin_ix(env: &TestEnv, data: Vec<u8>) -> Instruction {
+    Instruction {
+        program_id: dvpn::ID,
+        accounts: dvpn::accounts::SetMinStake {
+            authority: env.attestor.pubkey(),
+            state: env.state,
+        }
+        .to_account_metas(None),
+        data,
+    }
+}
+
+fn stake_tokens_ix(env: &TestEnv, operator: &Pubkey, node: &Pubkey, amount: u64) -> Instruction {
+    Instruction {
+        program_id: dvpn::ID,
+        accounts: dvpn::accounts::StakeTokens {
+            operator: *operator,
+            state: env.state,
+            node: *node,
+            dvpn_mint: env.mint,
+            vault: Pubkey::find_program_address(&[b"node_vault", node.as_ref()], &dvpn::ID).0,
+            operator_token_account: get_associated_token_address(operator, &env.mint),
+            token_program: spl_token::ID,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: dvpn::instruction::StakeTokens { amount }.data(),
+    }
+}
+
+#[tokio::test]
+async fn token_stake_activates_a_node_registered_below_the_minimum() {
+    let mut env = TestEnv::start().await;
+    let admin = env.attestor.insecure_clone();
+    let ixs = [
+        admin_ix(&env, dvpn::instruction::SetMinStake { min_stake_lamports: MIN_STAKE }.data()),
+        admin_ix(&env, dvpn::instruction::SetTokenStakeRate { token_stake_rate: TOKEN_STAKE_RATE }.data()),
+    ];
+    env.send(&ixs, &[&admin]).await.unwrap();
+
+    // A second operator with no SOL stake, holding protocol tokens worth the minimum
+    let operator = Keypair::new();
+    let payer = env.ctx.payer.pubkey();
+    let (node, _) = Pubkey::find_program_address(&[b"node", operator.pubkey().as_ref()], &dvpn::ID);
+    let operator_tokens = get_associated_token_address(&operator.pubkey(), &env.mint);
+    let setup = [
+        system_instruction::transfer(&payer, &operator.pubkey(), 10_000_000_000),
+        create_associated_token_account(&payer, &operator.pubkey(), &env.mint, &spl_token::ID),
+        spl_token::instruction::mint_to(&spl_token::ID, &env.mint, &operator_tokens, &admin.pubkey(), &[], MIN_STAKE)
+            .unwrap(),
+    ];
+    env.send(&setup, &[&admin]).await.unwrap();
+
+    let register_node = Instruction {
+        program_id: dvpn::ID,
+        accounts: dvpn::accounts::RegisterNode {
+            operator: operator.pubkey(),
+            state: env.state,
+            node,
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: dvpn::instruction::RegisterNode {
+            stake_lamports: 0,
+            bandwidth_mbps: 100,
+            meta_hash: [0; 32],
+            wg_pubkey: None,
+        }
+        .data(),
+    };
+    env.send(&[register_node], &[&operator]).await.unwrap();
+    assert!(!env.account::<dvpn::Node>(node).await.active);
+
+    // Half the minimum in tokens: still inactive, and reactivate_node refuses
+    let ix = stake_tokens_ix(&env, &operator.pubkey(), &node, MIN_STAKE / 2);
+    env.send(&[ix], &[&operator]).await.unwrap();
+    assert!(!env.account::<dvpn::Node>(node).await.active);
+    let reactivate = Instruction {
+        program_id: dvpn::ID,
+        accounts: dvpn::accounts::ReactivateNode {
+            operator: operator.pubkey(),
+            state: env.state,
+            node,
+        }
+        .to_account_metas(None),
+        data: dvpn::instruction::ReactivateNode {}.data(),
+    };
+    assert_dvpn_error(env.send(&[reactivate], &[&operator]).await, DvpnError::InsufficientStake);
+
+    // The rest brings the token credit up to the minimum and activates the node
+    let ix = stake_tokens_ix(&env, &operator.pubkey(), &node, MIN_STAKE / 2);
+    env.send(&[ix], &[&operator]).await.unwrap();
+    let node = env.account::<dvpn::Node>(node).await;
+    assert!(node.active);
+    assert_eq!(node.stake_lamports, 0);
+    assert_eq!(node.staked_tokens, MIN_STAKE);
+}
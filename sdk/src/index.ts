@@ -1,10 +1,13 @@
 import { AnchorProvider, Program, setProvider } from "@coral-xyz/anchor";
 import { Connection, PublicKey, SystemProgram } from "@solana/web3.js";
-import { TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import { ASSOCIATED_TOKEN_PROGRAM_ID, TOKEN_PROGRAM_ID, getAssociatedTokenAddressSync } from "@solana/spl-token";
 import { readFileSync } from "fs";
 import { fileURLToPath } from "url";
 import { dirname, join } from "path";
 import BN from "bn.js";
+import { fetchNextSessionId, getSessionCounterPda, getSessionPda } from "./pda.js";
+
+export { fetchNextSessionId, getSessionCounterPda, getSessionPda };
 
 const __filename = fileURLToPath(import.meta.url);
 const __dirname = dirname(__filename);
@@ -35,11 +38,21 @@ export class DvpnClient {
     return PublicKey.findProgramAddressSync([Buffer.from("node"), operator.toBuffer()], this.program.programId);
   }
 
-  sessionPda(user: PublicKey, node: PublicKey): [PublicKey, number] {
-    return PublicKey.findProgramAddressSync(
-      [Buffer.from("escrow"), user.toBuffer(), node.toBuffer()],
-      this.program.programId
-    );
+  attestorRegistryPda(state: PublicKey): [PublicKey, number] {
+    return PublicKey.findProgramAddressSync([Buffer.from("attestors"), state.toBuffer()], this.program.programId);
+  }
+
+  sessionCounterPda(user: PublicKey): [PublicKey, number] {
+    return getSessionCounterPda(user, this.program.programId);
+  }
+
+  // `node` is the node account, not its operator
+  sessionPda(user: PublicKey, node: PublicKey, sessionId: bigint | number): [PublicKey, number] {
+    return getSessionPda(user, node, sessionId, this.program.programId);
+  }
+
+  async nextSessionId(user: PublicKey): Promise<bigint> {
+    return fetchNextSessionId(this.connection, user, this.program.programId);
   }
 
   async initializeState(args: { authority: PublicKey; mint: PublicKey; rewardRateBps: number }) {
@@ -51,6 +64,7 @@ export class DvpnClient {
         state,
         stateSigner: state,
         dvpnMint: args.mint,
+        treasury: PublicKey.findProgramAddressSync([Buffer.from("treasury"), state.toBuffer()], this.program.programId)[0],
         systemProgram: SystemProgram.programId,
       })
       .rpc();
@@ -65,68 +79,91 @@ export class DvpnClient {
       .rpc();
   }
 
+  // Escrows `depositAmount` of `paymentMint` (an allowlisted mint the node has a rate for) for
+  // up to `maxDuration` seconds. Returns the new session and its id.
   async startSession(args: {
     user: PublicKey;
+    stateAuthority: PublicKey;
     nodeOperator: PublicKey;
-    depositAmount: number;
-    userTokenAccount: PublicKey;
-    escrowTokenAccount: PublicKey;
-  }) {
-    const [state] = this.statePda(args.user);
+    depositAmount: bigint;
+    maxDuration: number;
+    paymentMint: PublicKey;
+    tokenProgram?: PublicKey; // TOKEN_PROGRAM_ID unless the mint is Token-2022
+  }): Promise<{ session: PublicKey; sessionId: bigint }> {
+    const tokenProgram = args.tokenProgram ?? TOKEN_PROGRAM_ID;
+    const [state] = this.statePda(args.stateAuthority);
     const [node] = this.nodePda(args.nodeOperator);
-    const [session] = this.sessionPda(args.user, args.nodeOperator);
+    const [sessionCounter] = this.sessionCounterPda(args.user);
+    const sessionId = await this.nextSessionId(args.user);
+    const [session] = this.sessionPda(args.user, node, sessionId);
 
     await this.program.methods
-      .startSession(new BN(args.depositAmount))
+      .startSession(new BN(args.depositAmount.toString()), new BN(args.maxDuration))
       .accounts({
+        sessionCounter,
+        session,
         user: args.user,
         node,
-        session,
-        userTokenAccount: args.userTokenAccount,
-        escrowTokenAccount: args.escrowTokenAccount,
-        tokenProgram: TOKEN_PROGRAM_ID,
+        state,
+        paymentMint: args.paymentMint,
+        userTokenAccount: getAssociatedTokenAddressSync(args.paymentMint, args.user, false, tokenProgram),
+        escrowTokenAccount: getAssociatedTokenAddressSync(args.paymentMint, session, true, tokenProgram),
+        tokenProgram,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
         systemProgram: SystemProgram.programId,
       })
       .rpc();
+    return { session, sessionId };
   }
 
+  // Needs the registry threshold of attestors; co-signers beyond `attestor` go in `coSigners`
   async submitUsage(args: {
     attestor: PublicKey;
+    stateAuthority: PublicKey;
     user: PublicKey;
     nodeOperator: PublicKey;
-    bytes: number;
+    sessionId: bigint;
+    bytes: bigint;
+    coSigners?: PublicKey[];
   }) {
-    const [state] = this.statePda(args.attestor);
-    const [session] = this.sessionPda(args.user, args.nodeOperator);
+    const [state] = this.statePda(args.stateAuthority);
+    const [node] = this.nodePda(args.nodeOperator);
+    const [session] = this.sessionPda(args.user, node, args.sessionId);
 
     await this.program.methods
-      .submitUsage(new BN(args.bytes))
+      .submitUsage(new BN(args.bytes.toString()))
       .accounts({
         attestor: args.attestor,
         state,
+        attestorRegistry: this.attestorRegistryPda(state)[0],
         session,
+        user: args.user,
       })
+      .remainingAccounts((args.coSigners ?? []).map((pubkey) => ({ pubkey, isSigner: true, isWritable: false })))
       .rpc();
   }
 
+  // Ends the session and opens its dispute window; funds move later, in finalize_session.
+  // `authority` is the session user, the node operator or a registered attestor.
   async settleSession(args: {
+    authority: PublicKey;
+    stateAuthority: PublicKey;
     user: PublicKey;
     nodeOperator: PublicKey;
-    escrowTokenAccount: PublicKey;
-    nodeTokenAccount: PublicKey;
+    sessionId: bigint;
   }) {
+    const [state] = this.statePda(args.stateAuthority);
     const [node] = this.nodePda(args.nodeOperator);
-    const [session] = this.sessionPda(args.user, args.nodeOperator);
+    const [session] = this.sessionPda(args.user, node, args.sessionId);
 
     await this.program.methods
       .settleSession()
       .accounts({
-        user: args.user,
-        node,
         session,
-        escrowTokenAccount: args.escrowTokenAccount,
-        nodeTokenAccount: args.nodeTokenAccount,
-        tokenProgram: TOKEN_PROGRAM_ID,
+        node,
+        state,
+        attestorRegistry: this.attestorRegistryPda(state)[0],
+        authority: args.authority,
       })
       .rpc();
   }
@@ -142,8 +179,9 @@ export class DvpnClient {
     return await (this.program.account as any).node.fetch(node);
   }
 
-  async fetchSession(user: PublicKey, nodeOperator: PublicKey) {
-    const [session] = this.sessionPda(user, nodeOperator);
+  async fetchSession(user: PublicKey, nodeOperator: PublicKey, sessionId: bigint) {
+    const [node] = this.nodePda(nodeOperator);
+    const [session] = this.sessionPda(user, node, sessionId);
     try {
       return await (this.program.account as any).session.fetch(session);
     } catch (e) {
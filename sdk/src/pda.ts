@@ -0,0 +1,27 @@
+import { Connection, PublicKey } from "@solana/web3.js";
+
+// Kept free of the IDL so browser bundles can import it on its own ("@solvpn/sdk/pda")
+
+export function getSessionCounterPda(user: PublicKey, programId: PublicKey): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync([Buffer.from("session_counter"), user.toBuffer()], programId);
+}
+
+// Sessions are keyed by user, node account and the id taken from the user's session counter
+export function getSessionPda(
+  user: PublicKey,
+  node: PublicKey,
+  sessionId: bigint | number,
+  programId: PublicKey
+): [PublicKey, number] {
+  const id = Buffer.alloc(8);
+  id.writeBigUInt64LE(BigInt(sessionId));
+  return PublicKey.findProgramAddressSync([Buffer.from("session"), user.toBuffer(), node.toBuffer(), id], programId);
+}
+
+// Id the user's next start_session will use (0 if they never started one)
+export async function fetchNextSessionId(connection: Connection, user: PublicKey, programId: PublicKey): Promise<bigint> {
+  const [counterPda] = getSessionCounterPda(user, programId);
+  const accountInfo = await connection.getAccountInfo(counterPda);
+  if (!accountInfo) return 0n;
+  return accountInfo.data.readBigUInt64LE(8 + 32);
+}
@@ -71,13 +71,26 @@ async function verifyProofAndSettle(sessionPubkeyStr: string) {
 
   console.log(`✅ Proof verified! Total bytes: ${totalBytes}`);
 
-  // Create attestation signature
+  // Create attestation signature over the program's canonical settlement message:
+  // "dvpn:settle:v1" || session || user || node || total_bytes || nonce || expires_at (all LE)
+  const sessionAcc = await connection.getAccountInfo(sessionPubkey);
+  if (!sessionAcc) throw new Error("Session account not found");
+  const user = new PublicKey(sessionAcc.data.slice(8, 40));
+  const node = new PublicKey(sessionAcc.data.slice(40, 72));
+  const lastNonce = sessionAcc.data.readBigUInt64LE(98);
   const ts = Math.floor(Date.now() / 1000);
+  const nonce = lastNonce + 1n;
+  const expiresAt = BigInt(ts + 300);
+  const u64 = (v: bigint) => { const b = Buffer.alloc(8); b.writeBigUInt64LE(v); return b; };
+  const i64 = (v: bigint) => { const b = Buffer.alloc(8); b.writeBigInt64LE(v); return b; };
   const msg = Buffer.concat([
+    Buffer.from("dvpn:settle:v1"),
     sessionPubkey.toBuffer(),
-    Buffer.from(totalBytes.toString()),
-    attestor.publicKey.toBuffer(),
-    Buffer.from(ts.toString()),
+    user.toBuffer(),
+    node.toBuffer(),
+    u64(BigInt(totalBytes)),
+    u64(nonce),
+    i64(expiresAt),
   ]);
   const sig = nacl.sign.detached(msg, attestor.secretKey);
   const sig_b58 = bs58.encode(Buffer.from(sig));
@@ -86,13 +99,15 @@ async function verifyProofAndSettle(sessionPubkeyStr: string) {
   console.log(`📤 Submitting settlement to program...`);
 
   // For now, just log the attestation data
-  // In production, call settle_session_with_attestation instruction
+  // In production, send an Ed25519Program.createInstructionWithPublicKey(...) over `msg`
+  // immediately followed by settle_session_with_attestation(totalBytes, nonce, expiresAt)
   console.log({
     session: sessionPubkeyStr,
     totalBytes,
     attestorPubkey: attestor.publicKey.toString(),
     signature: sig_b58,
-    timestamp: ts,
+    nonce: nonce.toString(),
+    expiresAt: expiresAt.toString(),
   });
 
   console.log("✅ Settlement completed");
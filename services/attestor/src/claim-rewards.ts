@@ -1,7 +1,7 @@
 import { Connection, Keypair, PublicKey, Transaction, TransactionInstruction } from "@solana/web3.js";
 import dotenv from "dotenv";
 import { createHash } from "crypto";
-import { getOrCreateAssociatedTokenAccount, TOKEN_PROGRAM_ID, getMint, getAccount } from "@solana/spl-token";
+import { getOrCreateAssociatedTokenAccount, getMint, getAccount } from "@solana/spl-token";
 import { ASSOCIATED_TOKEN_PROGRAM_ID } from "@solana/spl-token";
 
 dotenv.config();
@@ -29,6 +29,10 @@ async function main() {
   if (!stateAcc) throw new Error("State not found");
   const offMint = 8 + 32 + 32;
   const mint = new PublicKey(stateAcc.data.slice(offMint, offMint + 32));
+  const mintAcc = await connection.getAccountInfo(mint);
+  if (!mintAcc) throw new Error("Mint not found");
+  // SPL Token or Token-2022, whichever owns the mint
+  const tokenProgram = mintAcc.owner;
 
   // Ensure operator ATA exists
   const ata = await getOrCreateAssociatedTokenAccount(
@@ -39,7 +43,7 @@ async function main() {
     true,
     "confirmed",
     { commitment: "confirmed" },
-    TOKEN_PROGRAM_ID,
+    tokenProgram,
     ASSOCIATED_TOKEN_PROGRAM_ID
   );
 
@@ -48,8 +52,8 @@ async function main() {
   if (!nodeAcc) throw new Error("Node account not found");
   const off = 8 + 32 + 4 + 32 + 8 + 8; // discriminator + operator + bandwidth + meta + stake + total_bytes
   const unclaimed = nodeAcc.data.readBigUInt64LE(off);
-  const mintInfo = await getMint(connection, mint);
-  const ataInfo = await getAccount(connection, ata.address);
+  const mintInfo = await getMint(connection, mint, "confirmed", tokenProgram);
+  const ataInfo = await getAccount(connection, ata.address, "confirmed", tokenProgram);
   const mintAccInfo = await connection.getAccountInfo(mint);
   const ataAccInfo = await connection.getAccountInfo(ata.address);
   console.log(
@@ -64,15 +68,32 @@ async function main() {
 
   const keys = [
     { pubkey: operator.publicKey, isSigner: true, isWritable: true },
-    { pubkey: statePda, isSigner: false, isWritable: false },
+    { pubkey: statePda, isSigner: false, isWritable: true },
     { pubkey: statePda, isSigner: false, isWritable: false }, // state_signer PDA (same address)
     { pubkey: mint, isSigner: false, isWritable: true },
     { pubkey: nodePda, isSigner: false, isWritable: true },
     { pubkey: ata.address, isSigner: false, isWritable: true },
-    { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+    { pubkey: tokenProgram, isSigner: false, isWritable: false },
     { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
   ];
 
+  // Append (epoch, node_epoch) pairs for every ended epoch with unclaimed usage
+  const now = BigInt(Math.floor(Date.now() / 1000));
+  const nextEpochId = stateAcc.data.readBigUInt64LE(342);
+  for (let id = 0n; id < nextEpochId; id++) {
+    const idBuf = Buffer.alloc(8);
+    idBuf.writeBigUInt64LE(id);
+    const [epochPda] = PublicKey.findProgramAddressSync([Buffer.from("epoch"), statePda.toBuffer(), idBuf], programId);
+    const [nodeEpochPda] = PublicKey.findProgramAddressSync([Buffer.from("node_epoch"), nodePda.toBuffer(), idBuf], programId);
+    const [epochAcc, nodeEpochAcc] = await connection.getMultipleAccountsInfo([epochPda, nodeEpochPda]);
+    if (!epochAcc || !nodeEpochAcc) continue;
+    if (epochAcc.data.readBigInt64LE(56) > now || nodeEpochAcc.data.readUInt8(56) !== 0) continue;
+    keys.push(
+      { pubkey: epochPda, isSigner: false, isWritable: false },
+      { pubkey: nodeEpochPda, isSigner: false, isWritable: true },
+    );
+  }
+
   const ix = new TransactionInstruction({ programId, keys, data });
   const tx = new Transaction().add(ix);
   const sig = await connection.sendTransaction(tx, [operator], { skipPreflight: false });
@@ -0,0 +1,45 @@
+import { Connection, Keypair, PublicKey, Transaction, TransactionInstruction } from "@solana/web3.js";
+import dotenv from "dotenv";
+import { createHash } from "crypto";
+
+dotenv.config();
+
+const RPC = process.env.SOLANA_RPC || "https://api.devnet.solana.com";
+const SECRET_KEY = process.env.INIT_AUTHORITY_SECRET_KEY!; // state authority (only used for PDA seeds and fees)
+const PROGRAM_ID = process.env.PROGRAM_ID!;
+
+async function main() {
+  const idArg = process.argv[2];
+  if (!idArg) throw new Error("usage: execute-proposal <proposal id>");
+  const payer = Keypair.fromSecretKey(Uint8Array.from(JSON.parse(SECRET_KEY)));
+  const connection = new Connection(RPC, "confirmed");
+  const programId = new PublicKey(PROGRAM_ID);
+
+  const [statePda] = PublicKey.findProgramAddressSync([Buffer.from("state"), payer.publicKey.toBuffer()], programId);
+  const [registryPda] = PublicKey.findProgramAddressSync([Buffer.from("attestors"), statePda.toBuffer()], programId);
+  const idBuf = Buffer.alloc(8);
+  idBuf.writeBigUInt64LE(BigInt(idArg));
+  const [proposalPda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("proposal"), statePda.toBuffer(), idBuf],
+    programId
+  );
+
+  // SetMint checks the current fee treasury (state.fee_treasury); pass the program id when unset
+  const stateAcc = await connection.getAccountInfo(statePda);
+  if (!stateAcc) throw new Error("State account not found");
+  const feeTreasury = new PublicKey(stateAcc.data.slice(117, 149));
+
+  const disc = createHash("sha256").update("global:execute_proposal").digest().subarray(0, 8);
+  const keys = [
+    { pubkey: statePda, isSigner: false, isWritable: true },
+    { pubkey: registryPda, isSigner: false, isWritable: true },
+    { pubkey: proposalPda, isSigner: false, isWritable: true },
+    { pubkey: feeTreasury.equals(PublicKey.default) ? programId : feeTreasury, isSigner: false, isWritable: false },
+  ];
+  const ix = new TransactionInstruction({ programId, keys, data: Buffer.from(disc) });
+  const sig = await connection.sendTransaction(new Transaction().add(ix), [payer], { skipPreflight: false });
+  await connection.confirmTransaction(sig, "confirmed");
+  console.log("Executed proposal", idArg, "tx:", sig);
+}
+
+main().catch((e) => { console.error(e); process.exit(1); });
@@ -0,0 +1,45 @@
+import { Connection, Keypair, PublicKey, Transaction, TransactionInstruction } from "@solana/web3.js";
+import dotenv from "dotenv";
+import { createHash } from "crypto";
+
+dotenv.config();
+
+const RPC = process.env.SOLANA_RPC || "https://api.devnet.solana.com";
+const SECRET_KEY = process.env.INIT_AUTHORITY_SECRET_KEY!; // operator uses same key for now
+const PROGRAM_ID = process.env.PROGRAM_ID!;
+// Keep well under the on-chain staleness window (15 minutes by default)
+const INTERVAL_MS = Number(process.env.HEARTBEAT_INTERVAL_MS || 5 * 60 * 1000);
+
+async function main() {
+  const operator = Keypair.fromSecretKey(Uint8Array.from(JSON.parse(SECRET_KEY)));
+  const connection = new Connection(RPC, "confirmed");
+  const programId = new PublicKey(PROGRAM_ID);
+
+  const [statePda] = PublicKey.findProgramAddressSync([Buffer.from("state"), operator.publicKey.toBuffer()], programId);
+  const [attestorRegistryPda] = PublicKey.findProgramAddressSync([Buffer.from("attestors"), statePda.toBuffer()], programId);
+  const [nodePda] = PublicKey.findProgramAddressSync([Buffer.from("node"), operator.publicKey.toBuffer()], programId);
+  const disc = createHash("sha256").update("global:heartbeat").digest().subarray(0, 8);
+  const startedAt = Date.now();
+
+  const beat = async () => {
+    // Option<u64>: 1 = Some, followed by the process uptime in seconds
+    const uptime = Buffer.alloc(8);
+    uptime.writeBigUInt64LE(BigInt(Math.floor((Date.now() - startedAt) / 1000)));
+    const data = Buffer.concat([disc, Buffer.from([1]), uptime]);
+    const keys = [
+      { pubkey: operator.publicKey, isSigner: true, isWritable: false },
+      { pubkey: statePda, isSigner: false, isWritable: false },
+      { pubkey: attestorRegistryPda, isSigner: false, isWritable: false },
+      { pubkey: nodePda, isSigner: false, isWritable: true },
+    ];
+    const tx = new Transaction().add(new TransactionInstruction({ programId, keys, data }));
+    const sig = await connection.sendTransaction(tx, [operator], { skipPreflight: false });
+    await connection.confirmTransaction(sig, "confirmed");
+    console.log("Heartbeat for node:", nodePda.toBase58(), "tx:", sig);
+  };
+
+  await beat();
+  setInterval(() => beat().catch((e) => console.error("heartbeat failed:", e)), INTERVAL_MS);
+}
+
+main().catch((e) => { console.error(e); process.exit(1); });
@@ -1,12 +1,12 @@
 import express from "express";
 import cors from "cors";
 import dotenv from "dotenv";
-import { Connection, Keypair, PublicKey, Transaction, TransactionInstruction, SystemProgram } from "@solana/web3.js";
+import { Connection, Ed25519Program, Keypair, PublicKey, Transaction, TransactionInstruction, SystemProgram } from "@solana/web3.js";
 import { createHash } from "crypto";
 import { z } from "zod";
 import { exec as execCb } from "node:child_process";
 import { promisify } from "node:util";
-import { getAssociatedTokenAddressSync, TOKEN_PROGRAM_ID, ASSOCIATED_TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import { getAssociatedTokenAddressSync, ASSOCIATED_TOKEN_PROGRAM_ID } from "@solana/spl-token";
 const exec = promisify(execCb);
 
 dotenv.config();
@@ -29,6 +29,115 @@ if (!process.env.PROGRAM_ID) {
 }
 const programId = new PublicKey(process.env.PROGRAM_ID);
 
+function findSessionCounterPda(user: PublicKey): [PublicKey, number] {
+  return PublicKey.findProgramAddressSync([Buffer.from("session_counter"), user.toBuffer()], programId);
+}
+
+function findSessionPda(user: PublicKey, node: PublicKey, sessionId: bigint): [PublicKey, number] {
+  const id = Buffer.alloc(8);
+  id.writeBigUInt64LE(sessionId);
+  return PublicKey.findProgramAddressSync([Buffer.from("session"), user.toBuffer(), node.toBuffer(), id], programId);
+}
+
+// SPL Token or Token-2022, whichever owns the mint; ATAs and CPIs must use the same one
+async function mintTokenProgram(mint: PublicKey): Promise<PublicKey> {
+  const acc = await connection.getAccountInfo(mint);
+  if (!acc) throw new Error("mint not found");
+  return acc.owner;
+}
+
+// state.payment_mints follows token_stake_rate; empty slots are the default pubkey
+const STATE_PAYMENT_MINTS_OFFSET = 454;
+const MAX_PAYMENT_MINTS = 4;
+
+function readPaymentMints(stateData: Buffer): PublicKey[] {
+  const mints: PublicKey[] = [];
+  for (let i = 0; i < MAX_PAYMENT_MINTS; i++) {
+    const start = STATE_PAYMENT_MINTS_OFFSET + 32 * i;
+    const mint = new PublicKey(stateData.slice(start, start + 32));
+    if (!mint.equals(PublicKey.default)) mints.push(mint);
+  }
+  return mints;
+}
+
+// Mint the session was paid in, appended after dispute_bond
+async function sessionPaymentMint(sessionPda: PublicKey): Promise<PublicKey> {
+  const acc = await connection.getAccountInfo(sessionPda);
+  if (!acc) throw new Error("Session account not found");
+  return new PublicKey(acc.data.slice(187, 219));
+}
+
+// SessionStatus::Disputed, from the status byte after user, node, deposit, bytes and start time
+const SESSION_STATUS_OFFSET = 96;
+const SESSION_STATUS_DISPUTED = 3;
+
+async function sessionIsDisputed(sessionPda: PublicKey): Promise<boolean> {
+  const acc = await connection.getAccountInfo(sessionPda);
+  if (!acc) throw new Error("Session account not found");
+  return acc.data[SESSION_STATUS_OFFSET] === SESSION_STATUS_DISPUTED;
+}
+
+// Protocol fees in the reward mint go to state.fee_treasury (after mint, reward rate, bump,
+// min stake, fee bps); other payment mints use the treasury PDA's ATA. No fee is charged
+// until fee_treasury is set, so the optional account is passed as the program id (None).
+function feeAccountFor(
+  stateData: Buffer,
+  treasuryPda: PublicKey,
+  mint: PublicKey,
+  tokenProgram: PublicKey,
+  programId: PublicKey
+): PublicKey {
+  const rewardMint = new PublicKey(stateData.slice(8 + 32 + 32, 8 + 32 + 32 + 32));
+  const feeTreasury = new PublicKey(stateData.slice(117, 149));
+  if (feeTreasury.equals(PublicKey.default)) return programId;
+  if (mint.equals(rewardMint)) return feeTreasury;
+  return getAssociatedTokenAddressSync(mint, treasuryPda, true, tokenProgram, ASSOCIATED_TOKEN_PROGRAM_ID);
+}
+
+// next_session_id sits after the discriminator and user pubkey; 0 if the counter doesn't exist yet
+async function readNextSessionId(counterPda: PublicKey): Promise<bigint> {
+  const acc = await connection.getAccountInfo(counterPda);
+  return acc ? acc.data.readBigUInt64LE(8 + 32) : 0n;
+}
+
+function findEpochPda(statePda: PublicKey, epochId: bigint): [PublicKey, number] {
+  const id = Buffer.alloc(8);
+  id.writeBigUInt64LE(epochId);
+  return PublicKey.findProgramAddressSync([Buffer.from("epoch"), statePda.toBuffer(), id], programId);
+}
+
+function findNodeEpochPda(nodePda: PublicKey, epochId: bigint): [PublicKey, number] {
+  const id = Buffer.alloc(8);
+  id.writeBigUInt64LE(epochId);
+  return PublicKey.findProgramAddressSync([Buffer.from("node_epoch"), nodePda.toBuffer(), id], programId);
+}
+
+// State.next_epoch_id; the open epoch is the last one created
+async function readNextEpochId(statePda: PublicKey): Promise<bigint> {
+  const acc = await connection.getAccountInfo(statePda);
+  if (!acc) throw new Error("state not found");
+  return acc.data.readBigUInt64LE(342);
+}
+
+// (epoch, node_epoch) pairs for ended epochs the node has unclaimed usage in
+async function claimableEpochAccounts(statePda: PublicKey, nodePda: PublicKey) {
+  const now = BigInt(Math.floor(Date.now() / 1000));
+  const nextEpochId = await readNextEpochId(statePda);
+  const metas = [];
+  for (let id = 0n; id < nextEpochId; id++) {
+    const [epochPda] = findEpochPda(statePda, id);
+    const [nodeEpochPda] = findNodeEpochPda(nodePda, id);
+    const [epochAcc, nodeEpochAcc] = await connection.getMultipleAccountsInfo([epochPda, nodeEpochPda]);
+    if (!epochAcc || !nodeEpochAcc) continue;
+    if (epochAcc.data.readBigInt64LE(56) > now || nodeEpochAcc.data.readUInt8(56) !== 0) continue;
+    metas.push(
+      { pubkey: epochPda, isSigner: false, isWritable: false },
+      { pubkey: nodeEpochPda, isSigner: false, isWritable: true },
+    );
+  }
+  return metas;
+}
+
 const app = express();
 app.use(cors());
 app.use(express.json());
@@ -52,16 +161,25 @@ app.post("/record-usage", async (req, res) => {
     // Derive PDAs
     const [statePda] = PublicKey.findProgramAddressSync([Buffer.from("state"), wallet.publicKey.toBuffer()], programId);
     const [nodePda] = PublicKey.findProgramAddressSync([Buffer.from("node"), operatorPk.toBuffer()], programId);
+    const [attestorRegistryPda] = PublicKey.findProgramAddressSync([Buffer.from("attestors"), statePda.toBuffer()], programId);
     // Build instruction data: discriminator + u64 bytes
     const disc = createHash("sha256").update("global:record_usage").digest().subarray(0, 8);
     let v = BigInt(bytes);
     const u64 = Buffer.alloc(8);
     for (let i = 0; i < 8; i++) { u64[i] = Number(v & 0xffn); v >>= 8n; }
     const data = Buffer.concat([disc, u64]);
+    const nextEpochId = await readNextEpochId(statePda);
+    if (nextEpochId === 0n) throw new Error("no reward epoch started");
+    const [epochPda] = findEpochPda(statePda, nextEpochId - 1n);
+    const [nodeEpochPda] = findNodeEpochPda(nodePda, nextEpochId - 1n);
     const keys = [
-      { pubkey: wallet.publicKey, isSigner: true, isWritable: false },
+      { pubkey: wallet.publicKey, isSigner: true, isWritable: true },
       { pubkey: statePda, isSigner: false, isWritable: true },
       { pubkey: nodePda, isSigner: false, isWritable: true },
+      { pubkey: attestorRegistryPda, isSigner: false, isWritable: false },
+      { pubkey: epochPda, isSigner: false, isWritable: true },
+      { pubkey: nodeEpochPda, isSigner: false, isWritable: true },
+      { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
     ];
     const ix = new TransactionInstruction({ programId, keys, data });
     const tx = new Transaction().add(ix);
@@ -136,20 +254,22 @@ app.post("/claim-tx", async (req, res) => {
     const acc = await connection.getAccountInfo(statePda);
     if (!acc) throw new Error("state not found");
     const mint = new PublicKey(acc.data.slice(8 + 32 + 32, 8 + 32 + 32 + 32));
+    const tokenProgram = await mintTokenProgram(mint);
 
-    const ata = getAssociatedTokenAddressSync(mint, operatorPk, true, TOKEN_PROGRAM_ID, ASSOCIATED_TOKEN_PROGRAM_ID);
+    const ata = getAssociatedTokenAddressSync(mint, operatorPk, true, tokenProgram, ASSOCIATED_TOKEN_PROGRAM_ID);
 
     const disc = createHash("sha256").update("global:claim_rewards").digest().subarray(0, 8);
     const data = Buffer.from(disc);
     const keys = [
       { pubkey: operatorPk, isSigner: true, isWritable: true },
-      { pubkey: statePda, isSigner: false, isWritable: false },
+      { pubkey: statePda, isSigner: false, isWritable: true },
       { pubkey: statePda, isSigner: false, isWritable: false }, // state_signer PDA (same address)
       { pubkey: mint, isSigner: false, isWritable: true },
       { pubkey: nodePda, isSigner: false, isWritable: true },
       { pubkey: ata, isSigner: false, isWritable: true },
-      { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+      { pubkey: tokenProgram, isSigner: false, isWritable: false },
       { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+      ...(await claimableEpochAccounts(statePda, nodePda)),
     ];
 
     const ix = new TransactionInstruction({ programId, keys, data });
@@ -170,7 +290,9 @@ app.get("/state", async (_req, res) => {
     const acc = await connection.getAccountInfo(statePda);
     if (!acc) return res.status(404).json({ ok: false, error: "state not found" });
     const mint = new PublicKey(acc.data.slice(8 + 32 + 32, 8 + 32 + 32 + 32));
-    res.json({ ok: true, state: statePda.toBase58(), mint: mint.toBase58(), authority: wallet.publicKey.toBase58() });
+    const tokenProgram = await mintTokenProgram(mint);
+    const paymentMints = readPaymentMints(acc.data).map(m => m.toBase58());
+    res.json({ ok: true, state: statePda.toBase58(), mint: mint.toBase58(), tokenProgram: tokenProgram.toBase58(), paymentMints, authority: wallet.publicKey.toBase58() });
   } catch (e: any) {
     res.status(500).json({ ok: false, error: e.message });
   }
@@ -207,7 +329,7 @@ app.post("/faucet", async (req, res) => {
       ok: true, 
       signature, 
       amount: 500_000_000,
-      message: `Sent 0.5 SOL for gas fees! You can now start VPN sessions. DVPN tokens are not needed for deposits - the session works without pre-minted tokens.` 
+      message: `Sent 0.5 SOL for gas fees! You can now start VPN sessions. Session deposits are escrowed in DVPN tokens.` 
     });
   } catch (e: any) {
     console.error("faucet error:", e);
@@ -243,28 +365,54 @@ const startSessionSchema = z.object({
   user: z.string(),
   node: z.string(),
   depositAmount: z.number(),
+  // Session lifetime in seconds; after this the user can cancel or anyone can force-settle
+  maxDuration: z.number().int().positive().default(24 * 60 * 60),
+  // Accepted payment mint to deposit in; defaults to the protocol token
+  paymentMint: z.string().optional(),
 });
 
 app.post("/start-session-tx", async (req, res) => {
   try {
-    const { user, node, depositAmount } = startSessionSchema.parse(req.body);
+    const { user, node, depositAmount, maxDuration, paymentMint } = startSessionSchema.parse(req.body);
     const userPk = new PublicKey(user);
     const nodePk = new PublicKey(node);
     
     const [statePda] = PublicKey.findProgramAddressSync([Buffer.from("state"), wallet.publicKey.toBuffer()], programId);
-    const [sessionPda] = PublicKey.findProgramAddressSync([Buffer.from("session"), userPk.toBuffer(), nodePk.toBuffer()], programId);
+    // Each session gets a fresh PDA from the user's session counter
+    const [counterPda] = findSessionCounterPda(userPk);
+    const sessionId = await readNextSessionId(counterPda);
+    const [sessionPda] = findSessionPda(userPk, nodePk, sessionId);
     
-    // Build start_session instruction (simplified - no token transfer)
+    // Payment mint must be on the state allowlist
+    const stateAcc = await connection.getAccountInfo(statePda);
+    if (!stateAcc) throw new Error("State account not found");
+    const mint = paymentMint ? new PublicKey(paymentMint) : new PublicKey(stateAcc.data.slice(8 + 32 + 32, 8 + 32 + 32 + 32));
+    if (!readPaymentMints(stateAcc.data).some(m => m.equals(mint))) throw new Error("Payment mint not accepted");
+    const tokenProgram = await mintTokenProgram(mint);
+
+    // Deposit moves from the user's ATA into an escrow ATA owned by the session PDA
+    const userTokenAccount = getAssociatedTokenAddressSync(mint, userPk, false, tokenProgram, ASSOCIATED_TOKEN_PROGRAM_ID);
+    const escrowTokenAccount = getAssociatedTokenAddressSync(mint, sessionPda, true, tokenProgram, ASSOCIATED_TOKEN_PROGRAM_ID);
+
+    // Build start_session instruction
     const disc = createHash("sha256").update("global:start_session").digest().subarray(0, 8);
     const amountBuf = Buffer.alloc(8);
     amountBuf.writeBigUInt64LE(BigInt(depositAmount));
-    const data = Buffer.concat([disc, amountBuf]);
+    const durationBuf = Buffer.alloc(8);
+    durationBuf.writeBigInt64LE(BigInt(maxDuration));
+    const data = Buffer.concat([disc, amountBuf, durationBuf]);
     
     const keys = [
+      { pubkey: counterPda, isSigner: false, isWritable: true },
       { pubkey: sessionPda, isSigner: false, isWritable: true },
       { pubkey: userPk, isSigner: true, isWritable: true },
       { pubkey: nodePk, isSigner: false, isWritable: true },
       { pubkey: statePda, isSigner: false, isWritable: false },
+      { pubkey: mint, isSigner: false, isWritable: false },
+      { pubkey: userTokenAccount, isSigner: false, isWritable: true },
+      { pubkey: escrowTokenAccount, isSigner: false, isWritable: true },
+      { pubkey: tokenProgram, isSigner: false, isWritable: false },
+      { pubkey: ASSOCIATED_TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
       { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
     ];
     
@@ -275,7 +423,7 @@ app.post("/start-session-tx", async (req, res) => {
     
     const b64 = tx.serialize({ requireAllSignatures: false, verifySignatures: false }).toString("base64");
     
-    res.json({ ok: true, tx: b64, sessionPda: sessionPda.toBase58() });
+    res.json({ ok: true, tx: b64, sessionPda: sessionPda.toBase58(), sessionId: sessionId.toString() });
   } catch (e: any) {
     console.error("start-session-tx error:", e);
     res.status(500).json({ ok: false, error: e.message });
@@ -283,55 +431,272 @@ app.post("/start-session-tx", async (req, res) => {
 });
 
 // Build settle_session transaction
+const rateNodeSchema = z.object({
+  user: z.string(),
+  node: z.string(),
+  rating: z.number().int().min(1).max(5),
+  // Defaults to the user's most recent session
+  sessionId: z.union([z.string(), z.number()]).optional(),
+});
+
+// Build an unsigned transaction for the user to sign: rate_node
+app.post("/rate-node-tx", async (req, res) => {
+  try {
+    const { user, node, rating, sessionId } = rateNodeSchema.parse(req.body);
+    const userPk = new PublicKey(user);
+    const nodePk = new PublicKey(node);
+
+    const [statePda] = PublicKey.findProgramAddressSync([Buffer.from("state"), wallet.publicKey.toBuffer()], programId);
+    let id: bigint;
+    if (sessionId !== undefined) {
+      id = BigInt(sessionId);
+    } else {
+      const next = await readNextSessionId(findSessionCounterPda(userPk)[0]);
+      if (next === 0n) throw new Error("User has no sessions");
+      id = next - 1n;
+    }
+    const [sessionPda] = findSessionPda(userPk, nodePk, id);
+
+    const disc = createHash("sha256").update("global:rate_node").digest().subarray(0, 8);
+    const data = Buffer.concat([disc, Buffer.from([rating])]);
+    const keys = [
+      { pubkey: userPk, isSigner: true, isWritable: false },
+      { pubkey: sessionPda, isSigner: false, isWritable: true },
+      { pubkey: nodePk, isSigner: false, isWritable: true },
+      { pubkey: statePda, isSigner: false, isWritable: false },
+    ];
+
+    const ix = new TransactionInstruction({ programId, keys, data });
+    const { blockhash } = await connection.getLatestBlockhash();
+    const tx = new Transaction({ feePayer: userPk, recentBlockhash: blockhash }).add(ix);
+    const b64 = tx.serialize({ requireAllSignatures: false, verifySignatures: false }).toString("base64");
+    res.json({ ok: true, tx: b64 });
+  } catch (e: any) {
+    console.error("rate-node-tx error:", e);
+    res.status(400).json({ ok: false, error: e.message });
+  }
+});
+
 const settleSessionSchema = z.object({
   user: z.string(),
   node: z.string(),
+  // Defaults to the user's most recent session
+  sessionId: z.union([z.string(), z.number()]).optional(),
 });
 
+async function resolveSessionPda(userPk: PublicKey, nodePk: PublicKey, sessionId?: string | number): Promise<PublicKey> {
+  let id: bigint;
+  if (sessionId !== undefined) {
+    id = BigInt(sessionId);
+  } else {
+    const next = await readNextSessionId(findSessionCounterPda(userPk)[0]);
+    if (next === 0n) throw new Error("User has no sessions");
+    id = next - 1n;
+  }
+  return findSessionPda(userPk, nodePk, id)[0];
+}
+
+// settle_session only opens the dispute window; finalize-session-tx pays out afterwards
 app.post("/settle-session-tx", async (req, res) => {
   try {
-    const { user, node } = settleSessionSchema.parse(req.body);
+    const { user, node, sessionId } = settleSessionSchema.parse(req.body);
     const userPk = new PublicKey(user);
     const nodePk = new PublicKey(node);
-    
+
     const [statePda] = PublicKey.findProgramAddressSync([Buffer.from("state"), wallet.publicKey.toBuffer()], programId);
-    const [sessionPda] = PublicKey.findProgramAddressSync([Buffer.from("session"), userPk.toBuffer(), nodePk.toBuffer()], programId);
-    
-    // Get mint from state
+    const sessionPda = await resolveSessionPda(userPk, nodePk, sessionId);
+
+    const [registryPda] = PublicKey.findProgramAddressSync([Buffer.from("attestors"), statePda.toBuffer()], programId);
+
+    const disc = createHash("sha256").update("global:settle_session").digest().subarray(0, 8);
+    const keys = [
+      { pubkey: sessionPda, isSigner: false, isWritable: true },
+      { pubkey: nodePk, isSigner: false, isWritable: false },
+      { pubkey: statePda, isSigner: false, isWritable: false },
+      { pubkey: registryPda, isSigner: false, isWritable: false },
+      { pubkey: userPk, isSigner: true, isWritable: false },
+    ];
+
+    const ix = new TransactionInstruction({ programId, keys, data: Buffer.from(disc) });
+    const { blockhash } = await connection.getLatestBlockhash();
+    const tx = new Transaction({ feePayer: userPk, recentBlockhash: blockhash }).add(ix);
+    const b64 = tx.serialize({ requireAllSignatures: false, verifySignatures: false }).toString("base64");
+
+    res.json({ ok: true, tx: b64 });
+  } catch (e: any) {
+    console.error("settle-session-tx error:", e);
+    res.status(500).json({ ok: false, error: e.message });
+  }
+});
+
+// Pay out a pending session once its dispute window has passed, or a disputed one nobody
+// resolved before its deadline (anyone may send it)
+app.post("/finalize-session-tx", async (req, res) => {
+  try {
+    const { user, node, sessionId } = settleSessionSchema.parse(req.body);
+    const userPk = new PublicKey(user);
+    const nodePk = new PublicKey(node);
+
+    const [statePda] = PublicKey.findProgramAddressSync([Buffer.from("state"), wallet.publicKey.toBuffer()], programId);
+    const sessionPda = await resolveSessionPda(userPk, nodePk, sessionId);
+
+    // Every token account must be in the session's payment mint
     const stateAcc = await connection.getAccountInfo(statePda);
     if (!stateAcc) throw new Error("State account not found");
-    const mint = new PublicKey(stateAcc.data.slice(8 + 32 + 32, 8 + 32 + 32 + 32));
-    
+    const mint = await sessionPaymentMint(sessionPda);
+    const tokenProgram = await mintTokenProgram(mint);
+
     // Get node operator pubkey from node account
     const nodeAcc = await connection.getAccountInfo(nodePk);
     if (!nodeAcc) throw new Error("Node account not found");
     const nodeOperator = new PublicKey(nodeAcc.data.slice(8, 40)); // operator pubkey at offset 8
-    
+
     // Calculate token accounts
-    const escrowTokenAccount = getAssociatedTokenAddressSync(mint, sessionPda, true, TOKEN_PROGRAM_ID, ASSOCIATED_TOKEN_PROGRAM_ID);
-    const nodeTokenAccount = getAssociatedTokenAddressSync(mint, nodeOperator, true, TOKEN_PROGRAM_ID, ASSOCIATED_TOKEN_PROGRAM_ID);
-    
-    const disc = createHash("sha256").update("global:settle_session").digest().subarray(0, 8);
-    const data = Buffer.from(disc);
-    
+    const escrowTokenAccount = getAssociatedTokenAddressSync(mint, sessionPda, true, tokenProgram, ASSOCIATED_TOKEN_PROGRAM_ID);
+    const nodeTokenAccount = getAssociatedTokenAddressSync(mint, nodeOperator, true, tokenProgram, ASSOCIATED_TOKEN_PROGRAM_ID);
+    const userTokenAccount = getAssociatedTokenAddressSync(mint, userPk, false, tokenProgram, ASSOCIATED_TOKEN_PROGRAM_ID);
+    const [treasuryPda] = PublicKey.findProgramAddressSync([Buffer.from("treasury"), statePda.toBuffer()], programId);
+    const feeTreasury = feeAccountFor(stateAcc.data, treasuryPda, mint, tokenProgram, programId);
+
+    // expire_dispute takes the same accounts as finalize_session
+    const method = (await sessionIsDisputed(sessionPda)) ? "expire_dispute" : "finalize_session";
+    const disc = createHash("sha256").update(`global:${method}`).digest().subarray(0, 8);
     const keys = [
       { pubkey: sessionPda, isSigner: false, isWritable: true },
       { pubkey: nodePk, isSigner: false, isWritable: true },
       { pubkey: userPk, isSigner: false, isWritable: false },
+      { pubkey: statePda, isSigner: false, isWritable: false },
+      { pubkey: mint, isSigner: false, isWritable: false },
       { pubkey: escrowTokenAccount, isSigner: false, isWritable: true },
       { pubkey: nodeTokenAccount, isSigner: false, isWritable: true },
-      { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
-      { pubkey: userPk, isSigner: true, isWritable: false }, // User must sign to settle their own session
+      { pubkey: userTokenAccount, isSigner: false, isWritable: true },
+      { pubkey: treasuryPda, isSigner: false, isWritable: true },
+      { pubkey: feeTreasury, isSigner: false, isWritable: true },
+      { pubkey: tokenProgram, isSigner: false, isWritable: false },
     ];
-    
-    const ix = new TransactionInstruction({ programId, keys, data });
+
+    const ix = new TransactionInstruction({ programId, keys, data: Buffer.from(disc) });
     const { blockhash } = await connection.getLatestBlockhash();
     const tx = new Transaction({ feePayer: userPk, recentBlockhash: blockhash }).add(ix);
     const b64 = tx.serialize({ requireAllSignatures: false, verifySignatures: false }).toString("base64");
-    
+
     res.json({ ok: true, tx: b64 });
   } catch (e: any) {
-    console.error("settle-session-tx error:", e);
+    console.error("finalize-session-tx error:", e);
+    res.status(500).json({ ok: false, error: e.message });
+  }
+});
+
+const receiptSchema = settleSessionSchema.extend({
+  cumulativeBytes: z.union([z.string(), z.number()]),
+  // Unix time after which the receipt can no longer settle; covered by the signature
+  expiresAt: z.union([z.string(), z.number()]),
+  // base64 Ed25519 signature by the session user over the receipt message
+  signature: z.string(),
+});
+
+// Build the node operator's payment-channel settlement: Ed25519 receipt check + settle_session_with_receipt
+app.post("/settle-receipt-tx", async (req, res) => {
+  try {
+    const { user, node, sessionId, cumulativeBytes, expiresAt, signature } = receiptSchema.parse(req.body);
+    const userPk = new PublicKey(user);
+    const nodePk = new PublicKey(node);
+
+    const [statePda] = PublicKey.findProgramAddressSync([Buffer.from("state"), wallet.publicKey.toBuffer()], programId);
+    const sessionPda = await resolveSessionPda(userPk, nodePk, sessionId);
+    const stateAcc = await connection.getAccountInfo(statePda);
+    if (!stateAcc) throw new Error("State account not found");
+    const mint = await sessionPaymentMint(sessionPda);
+    const tokenProgram = await mintTokenProgram(mint);
+    const nodeAcc = await connection.getAccountInfo(nodePk);
+    if (!nodeAcc) throw new Error("Node account not found");
+    const nodeOperator = new PublicKey(nodeAcc.data.slice(8, 40));
+
+    const escrowTokenAccount = getAssociatedTokenAddressSync(mint, sessionPda, true, tokenProgram, ASSOCIATED_TOKEN_PROGRAM_ID);
+    const nodeTokenAccount = getAssociatedTokenAddressSync(mint, nodeOperator, true, tokenProgram, ASSOCIATED_TOKEN_PROGRAM_ID);
+    const userTokenAccount = getAssociatedTokenAddressSync(mint, userPk, false, tokenProgram, ASSOCIATED_TOKEN_PROGRAM_ID);
+    const [treasuryPda] = PublicKey.findProgramAddressSync([Buffer.from("treasury"), statePda.toBuffer()], programId);
+    const feeTreasury = feeAccountFor(stateAcc.data, treasuryPda, mint, tokenProgram, programId);
+
+    const bytesBuf = Buffer.alloc(8);
+    bytesBuf.writeBigUInt64LE(BigInt(cumulativeBytes));
+    const expiryBuf = Buffer.alloc(8);
+    expiryBuf.writeBigInt64LE(BigInt(expiresAt));
+    const message = Buffer.concat([Buffer.from("dvpn:receipt:v2"), sessionPda.toBuffer(), bytesBuf, expiryBuf]);
+    const ed25519Ix = Ed25519Program.createInstructionWithPublicKey({
+      publicKey: userPk.toBytes(),
+      message,
+      signature: Buffer.from(signature, "base64"),
+    });
+
+    const disc = createHash("sha256").update("global:settle_session_with_receipt").digest().subarray(0, 8);
+    const keys = [
+      { pubkey: nodeOperator, isSigner: true, isWritable: false },
+      { pubkey: sessionPda, isSigner: false, isWritable: true },
+      { pubkey: nodePk, isSigner: false, isWritable: true },
+      { pubkey: userPk, isSigner: false, isWritable: false },
+      { pubkey: statePda, isSigner: false, isWritable: false },
+      { pubkey: mint, isSigner: false, isWritable: false },
+      { pubkey: escrowTokenAccount, isSigner: false, isWritable: true },
+      { pubkey: nodeTokenAccount, isSigner: false, isWritable: true },
+      { pubkey: userTokenAccount, isSigner: false, isWritable: true },
+      { pubkey: treasuryPda, isSigner: false, isWritable: true },
+      { pubkey: feeTreasury, isSigner: false, isWritable: true },
+      { pubkey: tokenProgram, isSigner: false, isWritable: false },
+      { pubkey: new PublicKey("Sysvar1nstructions1111111111111111111111111"), isSigner: false, isWritable: false },
+    ];
+    const settleIx = new TransactionInstruction({ programId, keys, data: Buffer.concat([disc, bytesBuf, expiryBuf]) });
+
+    const { blockhash } = await connection.getLatestBlockhash();
+    const tx = new Transaction({ feePayer: nodeOperator, recentBlockhash: blockhash }).add(ed25519Ix, settleIx);
+    const b64 = tx.serialize({ requireAllSignatures: false, verifySignatures: false }).toString("base64");
+
+    res.json({ ok: true, tx: b64 });
+  } catch (e: any) {
+    console.error("settle-receipt-tx error:", e);
+    res.status(500).json({ ok: false, error: e.message });
+  }
+});
+
+const disputeSessionSchema = settleSessionSchema.extend({
+  userBytes: z.union([z.string(), z.number()]),
+});
+
+// Build an unsigned transaction for the user to sign: dispute_session with their own byte count
+app.post("/dispute-session-tx", async (req, res) => {
+  try {
+    const { user, node, sessionId, userBytes } = disputeSessionSchema.parse(req.body);
+    const userPk = new PublicKey(user);
+    const nodePk = new PublicKey(node);
+
+    const [statePda] = PublicKey.findProgramAddressSync([Buffer.from("state"), wallet.publicKey.toBuffer()], programId);
+    const sessionPda = await resolveSessionPda(userPk, nodePk, sessionId);
+    const mint = await sessionPaymentMint(sessionPda);
+    const tokenProgram = await mintTokenProgram(mint);
+    const escrowTokenAccount = getAssociatedTokenAddressSync(mint, sessionPda, true, tokenProgram, ASSOCIATED_TOKEN_PROGRAM_ID);
+    const userTokenAccount = getAssociatedTokenAddressSync(mint, userPk, false, tokenProgram, ASSOCIATED_TOKEN_PROGRAM_ID);
+
+    const disc = createHash("sha256").update("global:dispute_session").digest().subarray(0, 8);
+    const bytesBuf = Buffer.alloc(8);
+    bytesBuf.writeBigUInt64LE(BigInt(userBytes));
+    const keys = [
+      { pubkey: userPk, isSigner: true, isWritable: false },
+      { pubkey: sessionPda, isSigner: false, isWritable: true },
+      { pubkey: statePda, isSigner: false, isWritable: false },
+      { pubkey: mint, isSigner: false, isWritable: false },
+      { pubkey: escrowTokenAccount, isSigner: false, isWritable: true },
+      { pubkey: userTokenAccount, isSigner: false, isWritable: true },
+      { pubkey: tokenProgram, isSigner: false, isWritable: false },
+    ];
+
+    const ix = new TransactionInstruction({ programId, keys, data: Buffer.concat([disc, bytesBuf]) });
+    const { blockhash } = await connection.getLatestBlockhash();
+    const tx = new Transaction({ feePayer: userPk, recentBlockhash: blockhash }).add(ix);
+    const b64 = tx.serialize({ requireAllSignatures: false, verifySignatures: false }).toString("base64");
+
+    res.json({ ok: true, tx: b64 });
+  } catch (e: any) {
+    console.error("dispute-session-tx error:", e);
     res.status(500).json({ ok: false, error: e.message });
   }
 });
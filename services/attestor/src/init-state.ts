@@ -23,6 +23,8 @@ async function main() {
     programId
   );
 
+  const [treasuryPda] = PublicKey.findProgramAddressSync([Buffer.from("treasury"), statePda.toBuffer()], programId);
+
   // Anchor instruction discriminator: sha256("global:initialize_state")[..8]
   const discriminator = createHash("sha256").update("global:initialize_state").digest().subarray(0, 8);
   const args = Buffer.alloc(1 + 2);
@@ -35,6 +37,7 @@ async function main() {
     { pubkey: statePda, isSigner: false, isWritable: true },
     { pubkey: statePda, isSigner: false, isWritable: false }, // state_signer PDA
     { pubkey: dvpnMint, isSigner: false, isWritable: false },
+    { pubkey: treasuryPda, isSigner: false, isWritable: true },
     { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
   ];
 
@@ -19,7 +19,10 @@ async function main() {
   const mint = new PublicKey(acc.data.slice(72, 104));
   const rewardRateBps = acc.data.readUInt16LE(104);
   const bump = acc.data.readUInt8(106);
-  console.log(JSON.stringify({ statePda: statePda.toBase58(), authority: authority.toBase58(), attestor: attestor.toBase58(), mint: mint.toBase58(), rewardRateBps, bump }, null, 2));
+  const maxSupply = acc.data.readBigUInt64LE(326).toString();
+  const emissionAllocated = acc.data.readBigUInt64LE(334).toString();
+  const nextEpochId = acc.data.readBigUInt64LE(342).toString();
+  console.log(JSON.stringify({ statePda: statePda.toBase58(), authority: authority.toBase58(), attestor: attestor.toBase58(), mint: mint.toBase58(), rewardRateBps, bump, maxSupply, emissionAllocated, nextEpochId }, null, 2));
 }
 
 main().catch((e) => { console.error(e); process.exit(1); });
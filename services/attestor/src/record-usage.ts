@@ -1,4 +1,4 @@
-import { Connection, Keypair, PublicKey, Transaction, TransactionInstruction } from "@solana/web3.js";
+import { Connection, Keypair, PublicKey, SystemProgram, Transaction, TransactionInstruction } from "@solana/web3.js";
 import dotenv from "dotenv";
 import { createHash } from "crypto";
 
@@ -24,6 +24,27 @@ async function main() {
     programId
   );
 
+  const [attestorRegistryPda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("attestors"), statePda.toBuffer()],
+    programId
+  );
+
+  // usage is recorded against the latest epoch (State.next_epoch_id - 1)
+  const stateAcc = await connection.getAccountInfo(statePda);
+  if (!stateAcc) throw new Error("state not found");
+  const nextEpochId = stateAcc.data.readBigUInt64LE(342);
+  if (nextEpochId === 0n) throw new Error("no reward epoch started");
+  const epochId = Buffer.alloc(8);
+  epochId.writeBigUInt64LE(nextEpochId - 1n);
+  const [epochPda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("epoch"), statePda.toBuffer(), epochId],
+    programId
+  );
+  const [nodeEpochPda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("node_epoch"), nodePda.toBuffer(), epochId],
+    programId
+  );
+
   const disc = createHash("sha256").update("global:record_usage").digest().subarray(0, 8);
   const u64le = Buffer.from(Uint8Array.from((() => {
     const n = BYTES; const a = new Uint8Array(8); let v = n; for (let i=0;i<8;i++){ a[i]=Number(v & 0xffn); v >>= 8n; } return a;
@@ -31,9 +52,13 @@ async function main() {
   const data = Buffer.concat([disc, u64le]);
 
   const keys = [
-    { pubkey: attestor.publicKey, isSigner: true, isWritable: false },
+    { pubkey: attestor.publicKey, isSigner: true, isWritable: true },
     { pubkey: statePda, isSigner: false, isWritable: true },
     { pubkey: nodePda, isSigner: false, isWritable: true },
+    { pubkey: attestorRegistryPda, isSigner: false, isWritable: false },
+    { pubkey: epochPda, isSigner: false, isWritable: true },
+    { pubkey: nodeEpochPda, isSigner: false, isWritable: true },
+    { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
   ];
   const ix = new TransactionInstruction({ programId, keys, data });
   const tx = new Transaction().add(ix);
@@ -26,18 +26,30 @@ async function main() {
   await setAuthority(connection, authority, mint, authority.publicKey, AuthorityType.MintTokens, statePda);
   console.log("Mint authority set to state PDA:", statePda.toBase58());
 
-  // call set_mint(new_mint)
-  const disc = createHash("sha256").update("global:set_mint").digest().subarray(0, 8);
-  const data = Buffer.concat([disc, mint.toBuffer()]);
+  // mint changes are timelocked: queue a SetMint proposal (GovernanceAction variant 2)
+  const stateAcc = await connection.getAccountInfo(statePda);
+  if (!stateAcc) throw new Error("state not found");
+  const proposalId = stateAcc.data.readBigUInt64LE(318); // State.next_proposal_id
+  const idBuf = Buffer.alloc(8);
+  idBuf.writeBigUInt64LE(proposalId);
+  const [proposalPda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("proposal"), statePda.toBuffer(), idBuf],
+    programId
+  );
+  const disc = createHash("sha256").update("global:queue_proposal").digest().subarray(0, 8);
+  const data = Buffer.concat([disc, Buffer.from([2]), mint.toBuffer()]);
   const keys = [
-    { pubkey: authority.publicKey, isSigner: true, isWritable: false },
+    { pubkey: authority.publicKey, isSigner: true, isWritable: true },
     { pubkey: statePda, isSigner: false, isWritable: true },
+    { pubkey: proposalPda, isSigner: false, isWritable: true },
+    { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
   ];
   const ix = new TransactionInstruction({ programId, keys, data });
   const tx = new Transaction().add(ix);
   const sig = await connection.sendTransaction(tx, [authority], { skipPreflight: false });
   await connection.confirmTransaction(sig, "confirmed");
-  console.log("State mint updated. tx:", sig);
+  console.log("Queued mint change as proposal", proposalId.toString(), "tx:", sig);
+  console.log("Run `npm run execute:proposal -- " + proposalId.toString() + "` once the timelock has passed.");
 
   console.log("DVPN_MINT for .env:", mint.toBase58());
 }
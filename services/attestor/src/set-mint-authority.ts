@@ -21,13 +21,18 @@ async function main() {
     programId
   );
 
-  const before = await getMint(connection, mint);
+  // Works for SPL Token and Token-2022 mints alike
+  const mintAcc = await connection.getAccountInfo(mint);
+  if (!mintAcc) throw new Error("Mint not found");
+  const tokenProgram = mintAcc.owner;
+
+  const before = await getMint(connection, mint, "confirmed", tokenProgram);
   console.log("Mint before:", { mintAuthority: before.mintAuthority?.toBase58() });
 
-  const sig = await setAuthority(connection, authority, mint, authority.publicKey, AuthorityType.MintTokens, statePda);
+  const sig = await setAuthority(connection, authority, mint, authority.publicKey, AuthorityType.MintTokens, statePda, [], undefined, tokenProgram);
   console.log("Set mint authority to state PDA:", statePda.toBase58(), "tx:", sig);
 
-  const after = await getMint(connection, mint);
+  const after = await getMint(connection, mint, "confirmed", tokenProgram);
   console.log("Mint after:", { mintAuthority: after.mintAuthority?.toBase58() });
 }
 